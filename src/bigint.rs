@@ -0,0 +1,369 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::eval::{Arithmetic, Environment, EvalError};
+use crate::expression::Expression;
+
+// The largest magnitude in limbs, 32768 bits or about 9864 decimal digits.
+// Anything bigger fails as an overflow, which keeps `100000!` or
+// `2 ** 100000000` from running for hours or exhausting memory.
+const MAX_LIMBS: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    // Little-endian base 2^32 limbs without trailing zeros; zero is empty.
+    magnitude: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn from_i64(n: i64) -> Self {
+        let m = n.unsigned_abs();
+        Self::from_parts(n < 0, vec![m as u32, (m >> 32) as u32])
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+        let m = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &limb| (acc << 32) | limb as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(m)
+        } else {
+            i64::try_from(m).ok()
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    // `None` above `MAX_LIMBS`.
+    fn bounded(negative: bool, magnitude: Vec<u32>) -> Option<Self> {
+        let value = Self::from_parts(negative, magnitude);
+        (value.magnitude.len() <= MAX_LIMBS).then_some(value)
+    }
+
+    fn from_parts(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        normalize(&mut magnitude);
+        BigInt {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        BigInt::from_i64(n)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.magnitude, &other.magnitude),
+            (true, true) => cmp_magnitude(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        let mut chunks = Vec::new();
+        let mut rest = self.magnitude.clone();
+        while !rest.is_empty() {
+            let (quotient, chunk) = divrem_small(&rest, 1_000_000_000);
+            chunks.push(chunk);
+            rest = quotient;
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        if let Some(first) = chunks.next() {
+            write!(f, "{}", first)?;
+        }
+        for chunk in chunks {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl Arithmetic for BigInt {
+    fn from_i64(n: i64) -> Self {
+        BigInt::from_i64(n)
+    }
+
+    fn try_from_i128(n: i128) -> Option<Self> {
+        let m = n.unsigned_abs();
+        let limbs = (0..4).map(|i| (m >> (32 * i)) as u32).collect();
+        Some(Self::from_parts(n < 0, limbs))
+    }
+
+    fn try_add(&self, rhs: &Self) -> Option<Self> {
+        if self.negative == rhs.negative {
+            return Self::bounded(
+                self.negative,
+                add_magnitude(&self.magnitude, &rhs.magnitude),
+            );
+        }
+        Some(match cmp_magnitude(&self.magnitude, &rhs.magnitude) {
+            Ordering::Less => {
+                Self::from_parts(rhs.negative, sub_magnitude(&rhs.magnitude, &self.magnitude))
+            }
+            _ => Self::from_parts(
+                self.negative,
+                sub_magnitude(&self.magnitude, &rhs.magnitude),
+            ),
+        })
+    }
+
+    fn try_sub(&self, rhs: &Self) -> Option<Self> {
+        self.try_add(&rhs.try_neg()?)
+    }
+
+    // A product has at most one limb fewer than its operands together, so
+    // one that would be far too long is never computed.
+    fn try_mul(&self, rhs: &Self) -> Option<Self> {
+        if self.magnitude.len() + rhs.magnitude.len() > MAX_LIMBS + 1 {
+            return None;
+        }
+        Self::bounded(
+            self.negative != rhs.negative,
+            mul_magnitude(&self.magnitude, &rhs.magnitude),
+        )
+    }
+
+    fn try_div(&self, rhs: &Self) -> Option<Self> {
+        let (quotient, _) = divrem_magnitude(&self.magnitude, &rhs.magnitude);
+        Some(Self::from_parts(self.negative != rhs.negative, quotient))
+    }
+
+    fn try_rem(&self, rhs: &Self) -> Option<Self> {
+        let (_, remainder) = divrem_magnitude(&self.magnitude, &rhs.magnitude);
+        Some(Self::from_parts(self.negative, remainder))
+    }
+
+    fn try_neg(&self) -> Option<Self> {
+        Some(Self::from_parts(!self.negative, self.magnitude.clone()))
+    }
+
+    fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    fn is_negative(&self) -> bool {
+        self.negative
+    }
+}
+
+fn normalize(limbs: &mut Vec<u32>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+// Requires a >= b.
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut diff = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += 1 << 32;
+            borrow = 1;
+        }
+        result.push(diff as u32);
+    }
+    normalize(&mut result);
+    result
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let cur = result[i + j] as u64 + x as u64 * y as u64 + carry;
+            result[i + j] = cur as u32;
+            carry = cur >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+    normalize(&mut result);
+    result
+}
+
+fn divrem_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder = 0u64;
+    for i in (0..a.len()).rev() {
+        let cur = (remainder << 32) | a[i] as u64;
+        quotient[i] = (cur / divisor as u64) as u32;
+        remainder = cur % divisor as u64;
+    }
+    normalize(&mut quotient);
+    (quotient, remainder as u32)
+}
+
+// Shift-and-subtract long division; `b` must be nonzero.
+fn divrem_magnitude(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_magnitude(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if let [divisor] = b {
+        let (quotient, remainder) = divrem_small(a, *divisor);
+        let mut remainder = vec![remainder];
+        normalize(&mut remainder);
+        return (quotient, remainder);
+    }
+
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+    for bit in (0..a.len() * 32).rev() {
+        let mut carry = (a[bit / 32] >> (bit % 32)) & 1;
+        for limb in remainder.iter_mut() {
+            let next = *limb >> 31;
+            *limb = (*limb << 1) | carry;
+            carry = next;
+        }
+        if carry > 0 {
+            remainder.push(carry);
+        }
+        if cmp_magnitude(&remainder, b) != Ordering::Less {
+            remainder = sub_magnitude(&remainder, b);
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    normalize(&mut quotient);
+    (quotient, remainder)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalValue {
+    I64(i64),
+    BigInt(BigInt),
+}
+
+impl fmt::Display for EvalValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalValue::I64(n) => write!(f, "{}", n),
+            EvalValue::BigInt(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    I64,
+    BigInt,
+}
+
+impl Expression {
+    /// Evaluates in `i64` and, only when that overflows, again with big
+    /// integers. A big result may have at most 32768 bits; anything larger
+    /// still fails with `EvalError::Overflow`.
+    pub fn evaluate_adaptive(&self) -> Result<(EvalValue, Precision), EvalError> {
+        let env = Environment::new();
+        match self.evaluate_with(&env) {
+            Ok(value) => Ok((EvalValue::I64(value), Precision::I64)),
+            Err(EvalError::Overflow(_)) => {
+                let value = self.evaluate_as::<BigInt>(&env)?;
+                Ok((EvalValue::BigInt(value), Precision::BigInt))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn adaptive(input: &str) -> Result<(String, Precision), EvalError> {
+        let expr = Parser::new(input)
+            .and_then(|mut parser| parser.parse())
+            .expect("test input parses");
+        expr.evaluate_adaptive()
+            .map(|(value, precision)| (value.to_string(), precision))
+    }
+
+    #[test]
+    fn adaptive_precision() {
+        assert_eq!(
+            adaptive("2 + 3 * 4"),
+            Ok(("14".to_string(), Precision::I64))
+        );
+        assert_eq!(
+            adaptive("2 ** 100"),
+            Ok((
+                "1267650600228229401496703205376".to_string(),
+                Precision::BigInt
+            ))
+        );
+        assert_eq!(adaptive("1 / 0"), Err(EvalError::DivisionByZero("/")));
+    }
+
+    #[test]
+    fn adaptive_results_are_capped() {
+        assert_eq!(
+            adaptive("100000!").map(|_| ()),
+            Err(EvalError::Overflow("!"))
+        );
+        assert!(adaptive("2 ** 100000000").is_err());
+        assert!(adaptive("2 ** 32766").is_ok());
+    }
+
+    #[test]
+    fn zero_factor_stays_in_i64() {
+        let zero = Ok(("0".to_string(), Precision::I64));
+        assert_eq!(adaptive("0 * (9223372036854775807 * 2)"), zero);
+        assert_eq!(adaptive("(9223372036854775807 * 2) * 0"), zero);
+        assert_eq!(adaptive("(1 / 0) * 0"), Err(EvalError::DivisionByZero("/")));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::expression::Expression;
+
+/// One move from a node to one of its children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Step {
+    Left,
+    Right,
+    /// The single operand of a negation or factorial.
+    Operand,
+    Argument(usize),
+    Condition,
+    Then,
+    Else,
+    /// The bound value and the body of a `let`.
+    Value,
+    Body,
+}
+
+/// Steps from the root to a node; empty for the root itself.
+pub type DiffPath = Vec<Step>;
+
+impl Expression {
+    /// Paths to the nodes where `self` and `other` differ, in pre-order.
+    /// Two nodes differ when their operators, numbers, names or argument
+    /// counts do; their subtrees are then not compared any further.
+    pub fn diff(&self, other: &Expression) -> Vec<DiffPath> {
+        let mut diffs = Vec::new();
+        self.diff_into(other, &mut Vec::new(), &mut diffs);
+        diffs
+    }
+
+    fn diff_into(&self, other: &Expression, path: &mut DiffPath, diffs: &mut Vec<DiffPath>) {
+        // Blanking out the children leaves only what the nodes themselves hold.
+        let blank = |_: &Expression| Expression::Number(0);
+        if self.map_children(blank) != other.map_children(blank) {
+            diffs.push(path.clone());
+            return;
+        }
+
+        let children = self.children().into_iter().zip(other.children());
+        for (i, (left, right)) in children.enumerate() {
+            path.push(self.child_step(i));
+            left.diff_into(right, path, diffs);
+            path.pop();
+        }
+    }
+
+    fn child_step(&self, index: usize) -> Step {
+        match (self, index) {
+            (Expression::Negation(_) | Expression::Factorial(_), _) => Step::Operand,
+            (Expression::Call { .. }, i) => Step::Argument(i),
+            (Expression::Conditional { .. }, 0) => Step::Condition,
+            (Expression::Conditional { .. }, 1) => Step::Then,
+            (Expression::Conditional { .. }, _) => Step::Else,
+            (Expression::Let { .. }, 0) => Step::Value,
+            (Expression::Let { .. }, _) => Step::Body,
+            (_, 0) => Step::Left,
+            (_, _) => Step::Right,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input)
+            .and_then(|mut parser| parser.parse())
+            .expect("test input parses")
+    }
+
+    #[test]
+    fn diff_paths() {
+        assert_eq!(parse("1 + 2").diff(&parse("1 + 3")), [vec![Step::Right]]);
+        assert_eq!(parse("1 + 2").diff(&parse("1 * 2")), [vec![]]);
+        assert!(parse("1 + 2").diff(&parse("(1 + 2)")).is_empty());
+        assert_eq!(
+            parse("-(x + max(1, 2))").diff(&parse("-(y + max(1, 3))")),
+            [
+                vec![Step::Operand, Step::Left],
+                vec![Step::Operand, Step::Right, Step::Argument(1)],
+            ]
+        );
+    }
+}
@@ -0,0 +1,1401 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::num::Wrapping;
+
+use crate::expression::Expression;
+
+/// Variable values, held in 128 bits so a session at `IntegerWidth::I128`
+/// can store any result it computes. A narrower evaluation that reads a value
+/// too big for it fails with `EvalError::Overflow("variable")`.
+pub type Environment = HashMap<String, i128>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    DivisionByZero(&'static str),
+    Overflow(&'static str),
+    UndefinedVariable(String),
+    InvalidOperand(&'static str),
+    UnknownFunction(String),
+    WrongArity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    Budget(u64),
+    /// A division with a nonzero remainder in `evaluate_exact`.
+    InexactDivision,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::DivisionByZero(op) => write!(f, "Division by zero in '{}'", op),
+            EvalError::Overflow(op) => write!(f, "Overflow in '{}'", op),
+            EvalError::UndefinedVariable(name) => write!(f, "Undefined variable: '{}'", name),
+            EvalError::InvalidOperand(op) => write!(f, "Invalid operand for '{}'", op),
+            EvalError::UnknownFunction(name) => write!(f, "Unknown function: '{}'", name),
+            EvalError::WrongArity {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Wrong number of arguments for '{}': expected {}, found {}",
+                name, expected, found
+            ),
+            EvalError::Budget(max_ops) => {
+                write!(f, "Exceeded the budget of {} operations", max_ops)
+            }
+            EvalError::InexactDivision => write!(f, "Division with a nonzero remainder"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// An error from `evaluate_located`, with a copy of the smallest subtree
+/// that raised it: the `10 / 0` in `1 + (10 / 0)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalFailure {
+    pub kind: EvalError,
+    pub expr: Expression,
+}
+
+impl fmt::Display for EvalFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} while evaluating `{}`", self.kind, self.expr)
+    }
+}
+
+impl std::error::Error for EvalFailure {}
+
+/// The value of every node, shaped like the tree it was computed from, so an
+/// annotated printout looks each one up instead of evaluating it again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ValueTree {
+    pub(crate) value: Result<i64, EvalError>,
+    pub(crate) children: Vec<ValueTree>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalReport {
+    pub value: Result<i64, EvalError>,
+    pub exact_divisions: bool,
+    pub max_magnitude: u64,
+    /// Nodes in the whole tree, including branches that were never evaluated.
+    pub node_count: usize,
+}
+
+pub(crate) trait Arithmetic: Clone + PartialOrd {
+    /// Whether `/` must divide evenly instead of truncating.
+    const EXACT_DIVISION: bool = false;
+
+    fn from_i64(n: i64) -> Self;
+
+    fn try_from_i64(n: i64) -> Option<Self> {
+        Some(Self::from_i64(n))
+    }
+
+    /// Converts a variable's value.
+    fn try_from_i128(n: i128) -> Option<Self> {
+        i64::try_from(n).ok().and_then(Self::try_from_i64)
+    }
+
+    fn try_add(&self, rhs: &Self) -> Option<Self>;
+    fn try_sub(&self, rhs: &Self) -> Option<Self>;
+    fn try_mul(&self, rhs: &Self) -> Option<Self>;
+    fn try_div(&self, rhs: &Self) -> Option<Self>;
+    fn try_rem(&self, rhs: &Self) -> Option<Self>;
+    fn try_neg(&self) -> Option<Self>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::from_i64(0)
+    }
+
+    fn is_negative(&self) -> bool {
+        *self < Self::from_i64(0)
+    }
+
+    fn is_integer(&self) -> bool {
+        true
+    }
+}
+
+macro_rules! checked_arithmetic {
+    ($($t:ty),*) => {$(
+        impl Arithmetic for $t {
+            // Only used for small constants; literals and variables go
+            // through `try_from_i64`.
+            fn from_i64(n: i64) -> Self {
+                n as $t
+            }
+
+            fn try_from_i64(n: i64) -> Option<Self> {
+                n.try_into().ok()
+            }
+
+            fn try_from_i128(n: i128) -> Option<Self> {
+                n.try_into().ok()
+            }
+
+            fn try_add(&self, rhs: &Self) -> Option<Self> {
+                self.checked_add(*rhs)
+            }
+
+            fn try_sub(&self, rhs: &Self) -> Option<Self> {
+                self.checked_sub(*rhs)
+            }
+
+            fn try_mul(&self, rhs: &Self) -> Option<Self> {
+                self.checked_mul(*rhs)
+            }
+
+            fn try_div(&self, rhs: &Self) -> Option<Self> {
+                self.checked_div(*rhs)
+            }
+
+            fn try_rem(&self, rhs: &Self) -> Option<Self> {
+                self.checked_rem(*rhs)
+            }
+
+            fn try_neg(&self) -> Option<Self> {
+                self.checked_neg()
+            }
+        }
+    )*};
+}
+
+checked_arithmetic!(i32, i64, i128);
+
+// Literals and variables that do not fit are truncated, just like the
+// results.
+macro_rules! wrapping_arithmetic {
+    ($($t:ty),*) => {$(
+        impl Arithmetic for Wrapping<$t> {
+            fn from_i64(n: i64) -> Self {
+                Wrapping(n as $t)
+            }
+
+            fn try_from_i128(n: i128) -> Option<Self> {
+                Some(Wrapping(n as $t))
+            }
+
+            fn try_add(&self, rhs: &Self) -> Option<Self> {
+                Some(*self + *rhs)
+            }
+
+            fn try_sub(&self, rhs: &Self) -> Option<Self> {
+                Some(*self - *rhs)
+            }
+
+            fn try_mul(&self, rhs: &Self) -> Option<Self> {
+                Some(*self * *rhs)
+            }
+
+            fn try_div(&self, rhs: &Self) -> Option<Self> {
+                Some(Wrapping(self.0.wrapping_div(rhs.0)))
+            }
+
+            fn try_rem(&self, rhs: &Self) -> Option<Self> {
+                Some(Wrapping(self.0.wrapping_rem(rhs.0)))
+            }
+
+            fn try_neg(&self) -> Option<Self> {
+                Some(-*self)
+            }
+        }
+    )*};
+}
+
+wrapping_arithmetic!(i32, i64, i128);
+
+// Checked `i64` arithmetic whose divisions must leave no remainder.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+struct Exact(i64);
+
+impl Arithmetic for Exact {
+    const EXACT_DIVISION: bool = true;
+
+    fn from_i64(n: i64) -> Self {
+        Exact(n)
+    }
+
+    fn try_add(&self, rhs: &Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Exact)
+    }
+
+    fn try_sub(&self, rhs: &Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Exact)
+    }
+
+    fn try_mul(&self, rhs: &Self) -> Option<Self> {
+        self.0.checked_mul(rhs.0).map(Exact)
+    }
+
+    fn try_div(&self, rhs: &Self) -> Option<Self> {
+        self.0.checked_div(rhs.0).map(Exact)
+    }
+
+    fn try_rem(&self, rhs: &Self) -> Option<Self> {
+        self.0.checked_rem(rhs.0).map(Exact)
+    }
+
+    fn try_neg(&self) -> Option<Self> {
+        self.0.checked_neg().map(Exact)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowMode {
+    #[default]
+    Checked,
+    Wrapping,
+}
+
+/// The integer type arithmetic is carried out in. Results are widened to
+/// `i128`, which holds a result of any width as it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntegerWidth {
+    I32,
+    #[default]
+    I64,
+    I128,
+}
+
+fn factorial<N: Arithmetic>(n: &N) -> Result<N, EvalError> {
+    if n.is_negative() || !n.is_integer() {
+        return Err(EvalError::InvalidOperand("!"));
+    }
+
+    let one = N::from_i64(1);
+    let mut acc = one.clone();
+    let mut k = N::from_i64(2);
+    // Wrapping products never overflow, but once they hold as many factors of
+    // two as the type has bits (from `66!` in 64 bits, `34!` in 32) they wrap
+    // to zero for good, so the loop stops there instead of counting up to a
+    // huge `n`.
+    while k <= *n && !acc.is_zero() {
+        acc = acc.try_mul(&k).ok_or(EvalError::Overflow("!"))?;
+        k = k.try_add(&one).ok_or(EvalError::Overflow("!"))?;
+    }
+    Ok(acc)
+}
+
+// Exponentiation by squaring. `exponent` is halved as `(e - e % 2) / 2` so
+// that exact division (as for rationals) still steps through the bits.
+fn power<N: Arithmetic>(base: &N, exponent: &N, op: &'static str) -> Result<N, EvalError> {
+    if exponent.is_negative() || !exponent.is_integer() {
+        return Err(EvalError::InvalidOperand(op));
+    }
+
+    let overflow = || EvalError::Overflow(op);
+    let two = N::from_i64(2);
+    let mut acc = N::from_i64(1);
+    let mut base = base.clone();
+    let mut exponent = exponent.clone();
+    while !exponent.is_zero() {
+        let bit = exponent.try_rem(&two).ok_or_else(overflow)?;
+        if !bit.is_zero() {
+            acc = acc.try_mul(&base).ok_or_else(overflow)?;
+        }
+        exponent = exponent
+            .try_sub(&bit)
+            .and_then(|even| even.try_div(&two))
+            .ok_or_else(overflow)?;
+        if !exponent.is_zero() {
+            base = base.try_mul(&base).ok_or_else(overflow)?;
+        }
+    }
+    Ok(acc)
+}
+
+// Floor square root by Newton's method. Starting from `n / 2`, which is never
+// below the root for n >= 2, the iterates decrease until they reach it.
+fn sqrt<N: Arithmetic>(n: &N) -> Result<N, EvalError> {
+    if n.is_negative() || !n.is_integer() {
+        return Err(EvalError::InvalidOperand("sqrt"));
+    }
+
+    let overflow = || EvalError::Overflow("sqrt");
+    let two = N::from_i64(2);
+    if *n < two {
+        return Ok(n.clone());
+    }
+    let mut x = n.try_div(&two).ok_or_else(overflow)?;
+    loop {
+        let next = n
+            .try_div(&x)
+            .and_then(|q| x.try_add(&q))
+            .and_then(|sum| sum.try_div(&two))
+            .ok_or_else(overflow)?;
+        if next >= x {
+            return Ok(x);
+        }
+        x = next;
+    }
+}
+
+// Floor logarithm, counting how often `n` can be divided by `base`.
+fn log<N: Arithmetic>(n: &N, base: i64, op: &'static str) -> Result<N, EvalError> {
+    let one = N::from_i64(1);
+    if *n < one || !n.is_integer() {
+        return Err(EvalError::InvalidOperand(op));
+    }
+
+    let base = N::from_i64(base);
+    let mut n = n.clone();
+    let mut count = N::from_i64(0);
+    while n >= base {
+        n = n.try_div(&base).ok_or(EvalError::Overflow(op))?;
+        count = count.try_add(&one).ok_or(EvalError::Overflow(op))?;
+    }
+    Ok(count)
+}
+
+/// The number of arguments the builtin `name` takes, or `None` if there is
+/// no such builtin.
+pub(crate) fn builtin_arity(name: &str) -> Option<usize> {
+    match name {
+        "abs" | "sqrt" | "log2" | "log10" => Some(1),
+        "min" | "max" | "gcd" | "pow" => Some(2),
+        _ => None,
+    }
+}
+
+fn call_builtin<N: Arithmetic>(name: &str, args: &[N]) -> Result<N, EvalError> {
+    let expected =
+        builtin_arity(name).ok_or_else(|| EvalError::UnknownFunction(name.to_string()))?;
+    if args.len() != expected {
+        return Err(EvalError::WrongArity {
+            name: name.to_string(),
+            expected,
+            found: args.len(),
+        });
+    }
+
+    match (name, args) {
+        ("abs", [v]) => abs(v, "abs"),
+        ("min", [a, b]) => Ok(if b < a { b } else { a }.clone()),
+        ("max", [a, b]) => Ok(if b > a { b } else { a }.clone()),
+        ("gcd", [a, b]) => {
+            let (mut a, mut b) = (a.clone(), b.clone());
+            while !b.is_zero() {
+                // Only `i64::MIN % -1` can fail here, and its remainder is 0.
+                let rem = a.try_rem(&b).unwrap_or_else(|| N::from_i64(0));
+                a = std::mem::replace(&mut b, rem);
+            }
+            abs(&a, "gcd")
+        }
+        ("pow", [base, exponent]) => power(base, exponent, "pow"),
+        ("sqrt", [v]) => sqrt(v),
+        ("log2", [v]) => log(v, 2, "log2"),
+        ("log10", [v]) => log(v, 10, "log10"),
+        _ => unreachable!("arity checked above for {}", name),
+    }
+}
+
+fn abs<N: Arithmetic>(n: &N, op: &'static str) -> Result<N, EvalError> {
+    if n.is_negative() {
+        n.try_neg().ok_or(EvalError::Overflow(op))
+    } else {
+        Ok(n.clone())
+    }
+}
+
+/// The names bound by the enclosing `let`s, innermost last, in front of the
+/// environment. Their values stay in `N`, so a rational or big value is
+/// bound exactly.
+struct Scope<'a, N> {
+    env: &'a Environment,
+    /// `None` hides a name whose value failed.
+    locals: Vec<(&'a str, Option<N>)>,
+}
+
+impl<'a, N: Arithmetic> Scope<'a, N> {
+    fn new(env: &'a Environment) -> Self {
+        Scope {
+            env,
+            locals: Vec::new(),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<N, EvalError> {
+        let undefined = || EvalError::UndefinedVariable(name.to_string());
+        match self.locals.iter().rev().find(|(local, _)| *local == name) {
+            Some((_, value)) => value.clone().ok_or_else(undefined),
+            None => {
+                let value = self.env.get(name).ok_or_else(undefined)?;
+                N::try_from_i128(*value).ok_or(EvalError::Overflow("variable"))
+            }
+        }
+    }
+
+    /// Whether `expr` reads a name bound by a `let`, so that its value
+    /// depends on where it occurs.
+    fn reads_local(&self, expr: &Expression) -> bool {
+        !self.locals.is_empty()
+            && expr.iter().any(|node| {
+                matches!(node, Expression::Variable(name)
+                    if self.locals.iter().any(|(local, _)| local == name))
+            })
+    }
+
+    /// Runs `body` the way the body of `let name = value in body` sees the
+    /// scope: with `name` bound to `value`, or undefined for `None`.
+    fn bind<R>(&mut self, name: &'a str, value: Option<N>, body: impl FnOnce(&mut Self) -> R) -> R {
+        self.locals.push((name, value));
+        let result = body(self);
+        self.locals.pop();
+        result
+    }
+}
+
+/// The factor that decides a product without the other one, if any. A zero
+/// factor decides the product on its own: the other operand is not
+/// evaluated when it comes second, and its overflow is ignored when it comes
+/// first, so `0 * (i64::MAX * 2)` and `(i64::MAX * 2) * 0` are both 0. Any
+/// other failure, as in `(1 / 0) * 0`, still fails.
+fn zero_product<N: Arithmetic, E>(
+    left: &Result<N, E>,
+    right: &Result<N, E>,
+    overflowed: impl Fn(&E) -> bool,
+) -> Option<N> {
+    match (left, right) {
+        (Ok(left), _) if left.is_zero() => Some(left.clone()),
+        (Ok(_), Ok(right)) if right.is_zero() => Some(right.clone()),
+        (Err(e), Ok(right)) if right.is_zero() && overflowed(e) => Some(right.clone()),
+        _ => None,
+    }
+}
+
+/// One way of evaluating a tree. `Expression::walk` drives it and holds the
+/// rules every strategy shares: only the branch a conditional picks is
+/// evaluated, a `let` binds its name for the body alone, and a zero factor
+/// decides a product as `zero_product` says. A failure below a node is
+/// passed up unchanged, without calling `leave` for the nodes above it.
+trait Strategy {
+    type Value: Arithmetic;
+    /// What a failure carries up the tree.
+    type Error;
+
+    fn overflowed(error: &Self::Error) -> bool;
+
+    /// A value to use for `expr` without evaluating it.
+    fn visit(&mut self, _expr: &Expression, _scope: &Scope<Self::Value>) -> Option<Self::Value> {
+        None
+    }
+
+    /// Applies the operator at `expr` to the values of its operands.
+    fn apply(
+        &mut self,
+        expr: &Expression,
+        values: &[Self::Value],
+    ) -> Result<Self::Value, EvalError> {
+        expr.apply(values)
+    }
+
+    /// Receives what `expr`, `depth` levels below the root, evaluated to.
+    fn leave(
+        &mut self,
+        expr: &Expression,
+        scope: &Scope<Self::Value>,
+        depth: usize,
+        value: Result<Self::Value, EvalError>,
+    ) -> Result<Self::Value, Self::Error>;
+}
+
+/// Evaluates in `N` and nothing more.
+struct Plain<N>(PhantomData<N>);
+
+impl<N: Arithmetic> Strategy for Plain<N> {
+    type Value = N;
+    type Error = EvalError;
+
+    fn overflowed(error: &EvalError) -> bool {
+        matches!(error, EvalError::Overflow(_))
+    }
+
+    fn leave(
+        &mut self,
+        _expr: &Expression,
+        _scope: &Scope<N>,
+        _depth: usize,
+        value: Result<N, EvalError>,
+    ) -> Result<N, EvalError> {
+        value
+    }
+}
+
+/// Tags a failure with the node that raised it.
+struct Located<N>(PhantomData<N>);
+
+impl<N: Arithmetic> Strategy for Located<N> {
+    type Value = N;
+    type Error = EvalFailure;
+
+    fn overflowed(error: &EvalFailure) -> bool {
+        matches!(error.kind, EvalError::Overflow(_))
+    }
+
+    fn leave(
+        &mut self,
+        expr: &Expression,
+        _scope: &Scope<N>,
+        _depth: usize,
+        value: Result<N, EvalError>,
+    ) -> Result<N, EvalFailure> {
+        value.map_err(|kind| EvalFailure {
+            kind,
+            expr: expr.clone(),
+        })
+    }
+}
+
+/// Spends one unit of `remaining` on every operator applied. A product
+/// decided by a zero factor costs nothing.
+struct Bounded {
+    remaining: u64,
+}
+
+impl Strategy for Bounded {
+    type Value = i64;
+    type Error = EvalError;
+
+    fn overflowed(error: &EvalError) -> bool {
+        Plain::<i64>::overflowed(error)
+    }
+
+    fn apply(&mut self, expr: &Expression, values: &[i64]) -> Result<i64, EvalError> {
+        // The limit is filled in by `evaluate_bounded`.
+        self.remaining = self.remaining.checked_sub(1).ok_or(EvalError::Budget(0))?;
+        expr.apply(values)
+    }
+
+    fn leave(
+        &mut self,
+        _expr: &Expression,
+        _scope: &Scope<i64>,
+        _depth: usize,
+        value: Result<i64, EvalError>,
+    ) -> Result<i64, EvalError> {
+        value
+    }
+}
+
+/// Looks subtrees up in, and records them to, the cache of `evaluate_memo`.
+struct Memo<'c> {
+    cache: &'c mut HashMap<Expression, Option<i64>>,
+}
+
+impl Strategy for Memo<'_> {
+    type Value = i64;
+    type Error = EvalError;
+
+    fn overflowed(error: &EvalError) -> bool {
+        Plain::<i64>::overflowed(error)
+    }
+
+    // A cached failure doesn't say why it failed, which a zero factor next
+    // to it needs to know, so only values are taken from the cache.
+    fn visit(&mut self, expr: &Expression, scope: &Scope<i64>) -> Option<i64> {
+        match self.cache.get(expr) {
+            Some(&Some(value)) if !scope.reads_local(expr) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn leave(
+        &mut self,
+        expr: &Expression,
+        scope: &Scope<i64>,
+        _depth: usize,
+        value: Result<i64, EvalError>,
+    ) -> Result<i64, EvalError> {
+        if !scope.reads_local(expr) {
+            self.cache
+                .insert(expr.clone(), value.as_ref().ok().copied());
+        }
+        value
+    }
+}
+
+/// Fills in an `EvalReport` while evaluating.
+struct Detailed<'r> {
+    report: &'r mut EvalReport,
+}
+
+impl Strategy for Detailed<'_> {
+    type Value = i64;
+    type Error = EvalError;
+
+    fn overflowed(error: &EvalError) -> bool {
+        Plain::<i64>::overflowed(error)
+    }
+
+    fn apply(&mut self, expr: &Expression, values: &[i64]) -> Result<i64, EvalError> {
+        if let (Expression::Division(..), &[l, r]) = (expr, values)
+            && l.checked_rem(r).is_some_and(|rem| rem != 0)
+        {
+            self.report.exact_divisions = false;
+        }
+        expr.apply(values)
+    }
+
+    fn leave(
+        &mut self,
+        _expr: &Expression,
+        _scope: &Scope<i64>,
+        _depth: usize,
+        value: Result<i64, EvalError>,
+    ) -> Result<i64, EvalError> {
+        if let Ok(value) = value {
+            self.report.max_magnitude = self.report.max_magnitude.max(value.unsigned_abs());
+        }
+        value
+    }
+}
+
+/// Prints a line of `write_trace` for every node.
+struct Trace<'o> {
+    out: &'o mut String,
+}
+
+impl Strategy for Trace<'_> {
+    type Value = i64;
+    type Error = EvalError;
+
+    fn overflowed(error: &EvalError) -> bool {
+        Plain::<i64>::overflowed(error)
+    }
+
+    fn leave(
+        &mut self,
+        expr: &Expression,
+        _scope: &Scope<i64>,
+        depth: usize,
+        value: Result<i64, EvalError>,
+    ) -> Result<i64, EvalError> {
+        // Literals need no annotation.
+        let indent = "  ".repeat(depth);
+        let line = match (expr, &value) {
+            (Expression::Number(n), _) => format!("{}{}\n", indent, n),
+            (_, Ok(v)) => format!("{}{} = {}\n", indent, expr.label(), v),
+            (_, Err(EvalError::Overflow(_))) => format!("{}{} = overflow\n", indent, expr.label()),
+            (_, Err(e)) => format!("{}{} = {}\n", indent, expr.label(), e),
+        };
+        self.out.push_str(&line);
+        value
+    }
+}
+
+impl Expression {
+    pub fn evaluate(&self) -> Result<i64, EvalError> {
+        self.evaluate_with(&Environment::new())
+    }
+
+    pub fn evaluate_with(&self, env: &Environment) -> Result<i64, EvalError> {
+        self.evaluate_as(env)
+    }
+
+    pub fn evaluate_mode(&self, mode: OverflowMode) -> Result<i64, EvalError> {
+        self.evaluate_mode_with(&Environment::new(), mode)
+    }
+
+    pub fn evaluate_mode_with(
+        &self,
+        env: &Environment,
+        mode: OverflowMode,
+    ) -> Result<i64, EvalError> {
+        match mode {
+            OverflowMode::Checked => self.evaluate_with(env),
+            OverflowMode::Wrapping => self.evaluate_as::<Wrapping<i64>>(env).map(|value| value.0),
+        }
+    }
+
+    pub fn evaluate_width_with(
+        &self,
+        env: &Environment,
+        mode: OverflowMode,
+        width: IntegerWidth,
+    ) -> Result<i128, EvalError> {
+        self.evaluate_located_width_with(env, mode, width)
+            .map_err(|failure| failure.kind)
+    }
+
+    /// Evaluates like `evaluate`, but a `/` that would truncate, as in
+    /// `7 / 2`, fails with `EvalError::InexactDivision` instead.
+    pub fn evaluate_exact(&self) -> Result<i64, EvalError> {
+        self.evaluate_exact_with(&Environment::new())
+    }
+
+    pub fn evaluate_exact_with(&self, env: &Environment) -> Result<i64, EvalError> {
+        self.evaluate_as::<Exact>(env).map(|value| value.0)
+    }
+
+    pub fn evaluate_i32(&self) -> Option<i32> {
+        self.evaluate_as(&Environment::new()).ok()
+    }
+
+    pub fn evaluate_i128(&self) -> Option<i128> {
+        self.evaluate_as(&Environment::new()).ok()
+    }
+
+    pub(crate) fn evaluate_as<N: Arithmetic>(&self, env: &Environment) -> Result<N, EvalError> {
+        self.walk(&mut Plain(PhantomData), &mut Scope::new(env), 0)
+    }
+
+    fn walk<'a, S: Strategy>(
+        &'a self,
+        strategy: &mut S,
+        scope: &mut Scope<'a, S::Value>,
+        depth: usize,
+    ) -> Result<S::Value, S::Error> {
+        if let Some(value) = strategy.visit(self, scope) {
+            return Ok(value);
+        }
+        // A failure below this node is passed up unchanged by `?`.
+        let value = match self {
+            Expression::Number(n) => {
+                S::Value::try_from_i64(*n).ok_or(EvalError::Overflow("literal"))
+            }
+            Expression::Variable(name) => scope.lookup(name),
+            Expression::Let { name, value, body } => {
+                let value = value.walk(strategy, scope, depth + 1)?;
+                Ok(scope.bind(name, Some(value), |scope| {
+                    body.walk(strategy, scope, depth + 1)
+                })?)
+            }
+            Expression::Conditional { cond, then, els } => {
+                let branch = if cond.walk(strategy, scope, depth + 1)?.is_zero() {
+                    els
+                } else {
+                    then
+                };
+                Ok(branch.walk(strategy, scope, depth + 1)?)
+            }
+            Expression::Multiplication(left, right) => {
+                match left.walk(strategy, scope, depth + 1) {
+                    Ok(left) if left.is_zero() => Ok(left),
+                    left => {
+                        let right = right.walk(strategy, scope, depth + 1);
+                        match zero_product(&left, &right, S::overflowed) {
+                            Some(zero) => Ok(zero),
+                            None => strategy.apply(self, &[left?, right?]),
+                        }
+                    }
+                }
+            }
+            _ => {
+                let values = self
+                    .children()
+                    .into_iter()
+                    .map(|child| child.walk(strategy, scope, depth + 1))
+                    .collect::<Result<Vec<_>, _>>()?;
+                strategy.apply(self, &values)
+            }
+        };
+        strategy.leave(self, scope, depth, value)
+    }
+
+    /// Evaluates like `evaluate`, but a failure also carries the node where
+    /// it happened. A variable that is not defined is its own subtree.
+    pub fn evaluate_located(&self) -> Result<i64, EvalFailure> {
+        self.evaluate_located_with(&Environment::new())
+    }
+
+    pub fn evaluate_located_with(&self, env: &Environment) -> Result<i64, EvalFailure> {
+        self.evaluate_located_as(env)
+    }
+
+    /// Like `evaluate_located_with`, in the given mode and width, and
+    /// widened to `i128` like `evaluate_width_with`.
+    pub fn evaluate_located_width_with(
+        &self,
+        env: &Environment,
+        mode: OverflowMode,
+        width: IntegerWidth,
+    ) -> Result<i128, EvalFailure> {
+        match (mode, width) {
+            (OverflowMode::Checked, IntegerWidth::I32) => {
+                self.evaluate_located_as::<i32>(env).map(i128::from)
+            }
+            (OverflowMode::Wrapping, IntegerWidth::I32) => self
+                .evaluate_located_as::<Wrapping<i32>>(env)
+                .map(|value| value.0.into()),
+            (OverflowMode::Checked, IntegerWidth::I64) => {
+                self.evaluate_located_as::<i64>(env).map(i128::from)
+            }
+            (OverflowMode::Wrapping, IntegerWidth::I64) => self
+                .evaluate_located_as::<Wrapping<i64>>(env)
+                .map(|value| value.0.into()),
+            (OverflowMode::Checked, IntegerWidth::I128) => self.evaluate_located_as::<i128>(env),
+            (OverflowMode::Wrapping, IntegerWidth::I128) => self
+                .evaluate_located_as::<Wrapping<i128>>(env)
+                .map(|value| value.0),
+        }
+    }
+
+    fn evaluate_located_as<N: Arithmetic>(&self, env: &Environment) -> Result<N, EvalFailure> {
+        self.walk(&mut Located(PhantomData), &mut Scope::new(env), 0)
+    }
+
+    /// Evaluates every node in one bottom-up pass, children in `children()`
+    /// order. Unlike `evaluate`, both branches of a conditional get a value,
+    /// though only the one the condition picks becomes the node's, and the
+    /// body of a `let` sees the bound name unless its value failed.
+    pub(crate) fn value_tree(&self, env: &Environment) -> ValueTree {
+        self.value_tree_scoped(&mut Scope::new(env))
+    }
+
+    fn value_tree_scoped<'a>(&'a self, scope: &mut Scope<'a, i64>) -> ValueTree {
+        let children: Vec<ValueTree> = match self {
+            Expression::Let { name, value, body } => {
+                let value = value.value_tree_scoped(scope);
+                let bound = value.value.as_ref().ok().copied();
+                let body = scope.bind(name, bound, |scope| body.value_tree_scoped(scope));
+                vec![value, body]
+            }
+            _ => self
+                .children()
+                .into_iter()
+                .map(|child| child.value_tree_scoped(scope))
+                .collect(),
+        };
+        let value = match (self, children.as_slice()) {
+            (Expression::Number(n), _) => Ok(*n),
+            (Expression::Variable(name), _) => scope.lookup(name),
+            // A failed value fails the `let` even where its body doesn't
+            // need it.
+            (Expression::Let { .. }, [value, body]) => value.value.clone().and(body.value.clone()),
+            (Expression::Conditional { .. }, [cond, then, els]) => match cond.value {
+                Ok(0) => els.value.clone(),
+                Ok(_) => then.value.clone(),
+                Err(ref e) => Err(e.clone()),
+            },
+            (Expression::Multiplication(..), [left, right]) => {
+                match zero_product(&left.value, &right.value, Plain::<i64>::overflowed) {
+                    Some(zero) => Ok(zero),
+                    None => left
+                        .value
+                        .clone()
+                        .and_then(|l| right.value.clone().and_then(|r| self.apply(&[l, r]))),
+                }
+            }
+            _ => children
+                .iter()
+                .map(|child| child.value.clone())
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|values| self.apply(&values)),
+        };
+        ValueTree { value, children }
+    }
+
+    pub fn evaluate_clamped(&self, lo: i64, hi: i64) -> Result<i64, EvalError> {
+        let value = self.evaluate_with(&Environment::new())?;
+        Ok(value.clamp(lo, hi))
+    }
+
+    /// Evaluates like `evaluate`, but gives up with `EvalError::Budget` once
+    /// more than `max_ops` operators have been applied.
+    pub fn evaluate_bounded(&self, max_ops: u64) -> Result<i64, EvalError> {
+        let mut strategy = Bounded { remaining: max_ops };
+        self.walk(&mut strategy, &mut Scope::new(&Environment::new()), 0)
+            .map_err(|e| match e {
+                EvalError::Budget(_) => EvalError::Budget(max_ops),
+                e => e,
+            })
+    }
+
+    /// Evaluates like `evaluate`, but looks every subtree up in `cache` first
+    /// and records its value there afterwards, so identical subtrees are only
+    /// computed once. Failures are cached as `None` and computed again when
+    /// met a second time. A subtree that reads a `let`-bound name is never
+    /// cached, since its value depends on the binding.
+    pub fn evaluate_memo(&self, cache: &mut HashMap<Expression, Option<i64>>) -> Option<i64> {
+        self.walk(&mut Memo { cache }, &mut Scope::new(&Environment::new()), 0)
+            .ok()
+    }
+
+    fn apply<N: Arithmetic>(&self, values: &[N]) -> Result<N, EvalError> {
+        match (self, values) {
+            (Expression::Addition(..), [l, r]) => l.try_add(r).ok_or(EvalError::Overflow("+")),
+            (Expression::Subtraction(..), [l, r]) => l.try_sub(r).ok_or(EvalError::Overflow("-")),
+            (Expression::Multiplication(..), [l, r]) => {
+                l.try_mul(r).ok_or(EvalError::Overflow("*"))
+            }
+            (Expression::Division(..), [l, r]) => {
+                if r.is_zero() {
+                    return Err(EvalError::DivisionByZero("/"));
+                }
+                // `i64::MIN / -1` has no remainder and is left to overflow.
+                if N::EXACT_DIVISION && l.try_rem(r).is_some_and(|rem| !rem.is_zero()) {
+                    return Err(EvalError::InexactDivision);
+                }
+                l.try_div(r).ok_or(EvalError::Overflow("/"))
+            }
+            (Expression::Remainder(..), [l, r]) => {
+                if r.is_zero() {
+                    return Err(EvalError::DivisionByZero("%"));
+                }
+                l.try_rem(r).ok_or(EvalError::Overflow("%"))
+            }
+            (Expression::Power(..), [base, exponent]) => power(base, exponent, "**"),
+            (Expression::Negation(_), [v]) => v.try_neg().ok_or(EvalError::Overflow("-")),
+            (Expression::Factorial(_), [v]) => factorial(v),
+            (Expression::Call { name, .. }, args) => call_builtin(name, args),
+            (Expression::Comparison { op, .. }, [l, r]) => Ok(N::from_i64(op.holds(l, r) as i64)),
+            _ => unreachable!("operand count does not match {:?}", self),
+        }
+    }
+
+    pub fn evaluate_detailed(&self) -> EvalReport {
+        let mut report = EvalReport {
+            value: Ok(0),
+            exact_divisions: true,
+            max_magnitude: 0,
+            node_count: self.node_count(),
+        };
+        let strategy = &mut Detailed {
+            report: &mut report,
+        };
+        let value = self.walk(strategy, &mut Scope::new(&Environment::new()), 0);
+        report.value = value;
+        report
+    }
+
+    /// Evaluates like `evaluate` and prints every operator with its value as
+    /// the recursion unwinds, children first, indented by two spaces per
+    /// level starting at `depth`: `* = 6`. A failing node prints `overflow`
+    /// or the error instead, and nothing above it is printed.
+    pub fn evaluate_trace(&self, depth: usize) -> Result<i64, EvalError> {
+        let mut out = String::new();
+        let value = self.write_trace(&mut out, &Environment::new(), depth);
+        print!("{}", out);
+        value
+    }
+
+    /// Like `evaluate_trace`, appending the lines to `out` and looking
+    /// variables up in `env`.
+    pub fn write_trace(
+        &self,
+        out: &mut String,
+        env: &Environment,
+        depth: usize,
+    ) -> Result<i64, EvalError> {
+        self.walk(&mut Trace { out }, &mut Scope::new(env), depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input)
+            .and_then(|mut parser| parser.parse())
+            .expect("test input parses")
+    }
+
+    fn eval(input: &str) -> Result<i64, EvalError> {
+        parse(input).evaluate()
+    }
+
+    #[test]
+    fn factorial() {
+        assert_eq!(eval("5!"), Ok(120));
+        assert_eq!(eval("0!"), Ok(1));
+        assert_eq!(eval("(2+1)!"), Ok(6));
+        assert_eq!(eval("25!"), Err(EvalError::Overflow("!")));
+    }
+
+    #[test]
+    fn detailed_report() {
+        assert_eq!(
+            parse("8 / 2 + 3").evaluate_detailed(),
+            EvalReport {
+                value: Ok(7),
+                exact_divisions: true,
+                max_magnitude: 8,
+                node_count: 5,
+            }
+        );
+        // The untaken branch and the factor after a zero still count.
+        assert_eq!(
+            parse("0 ? 1 / 0 : 0 * (2 + 3)")
+                .evaluate_detailed()
+                .node_count,
+            10
+        );
+        assert_eq!(parse("(1 / 0) + (2 + 3)").evaluate_detailed().node_count, 7);
+    }
+
+    #[test]
+    fn clamped() {
+        assert_eq!(parse("2 - 10").evaluate_clamped(0, 100), Ok(0));
+        assert_eq!(parse("2 * 10").evaluate_clamped(0, 100), Ok(20));
+        assert_eq!(parse("20 * 10").evaluate_clamped(0, 100), Ok(100));
+    }
+
+    #[test]
+    fn checked_and_wrapping_modes() {
+        let expr = parse("9223372036854775807 + 1");
+        assert_eq!(
+            expr.evaluate_mode(OverflowMode::Checked),
+            Err(EvalError::Overflow("+"))
+        );
+        assert_eq!(expr.evaluate_mode(OverflowMode::Wrapping), Ok(i64::MIN));
+    }
+
+    #[test]
+    fn wrapping_factorial_stops_at_zero() {
+        // The wrapping product is 0 from 66! on, so this returns at once.
+        let expr = parse("10000000000000!");
+        assert_eq!(expr.evaluate_mode(OverflowMode::Wrapping), Ok(0));
+        assert_eq!(
+            parse("20!").evaluate_mode(OverflowMode::Wrapping),
+            Ok(2432902008176640000)
+        );
+    }
+
+    #[test]
+    fn builtin_functions() {
+        assert_eq!(eval("min(3, -4)"), Ok(-4));
+        assert_eq!(eval("max(3, -4)"), Ok(3));
+        assert_eq!(eval("abs(-7)"), Ok(7));
+        assert_eq!(eval("gcd(12, -18)"), Ok(6));
+        assert_eq!(eval("gcd(0, 0)"), Ok(0));
+        assert_eq!(
+            eval("nosuchfn(1)"),
+            Err(EvalError::UnknownFunction("nosuchfn".to_string()))
+        );
+        assert_eq!(
+            eval("max(1)"),
+            Err(EvalError::WrongArity {
+                name: "max".to_string(),
+                expected: 2,
+                found: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn division_by_zero_is_not_overflow() {
+        assert_eq!(eval("1/0"), Err(EvalError::DivisionByZero("/")));
+        assert_eq!(eval("1%0"), Err(EvalError::DivisionByZero("%")));
+        assert_eq!(
+            eval("9223372036854775807 + 1"),
+            Err(EvalError::Overflow("+"))
+        );
+    }
+
+    #[test]
+    fn conditional_evaluates_only_the_branch_taken() {
+        assert_eq!(eval("1 ? 2 : 1 / 0"), Ok(2));
+        assert_eq!(eval("0 ? 1 / 0 : 3"), Ok(3));
+        assert_eq!(eval("1 / 0 ? 1 : 2"), Err(EvalError::DivisionByZero("/")));
+    }
+
+    #[test]
+    fn bounded_evaluation() {
+        let huge = (0..200).fold(Expression::num(0), |acc, n| {
+            Expression::add(acc, Expression::num(n))
+        });
+        assert_eq!(huge.evaluate_bounded(100), Err(EvalError::Budget(100)));
+        assert_eq!(huge.evaluate_bounded(200), Ok(19900));
+        assert_eq!(parse("1 + 2 * 3").evaluate_bounded(2), Ok(7));
+        assert_eq!(
+            parse("1 + 2 * 3").evaluate_bounded(1),
+            Err(EvalError::Budget(1))
+        );
+    }
+
+    #[test]
+    fn integer_widths() {
+        // 40000 * 40000 still fits in i32, so a larger product is used.
+        let expr = parse("50000 * 50000");
+        let with = |mode, width| expr.evaluate_width_with(&Environment::new(), mode, width);
+        assert_eq!(
+            with(OverflowMode::Checked, IntegerWidth::I32),
+            Err(EvalError::Overflow("*"))
+        );
+        assert_eq!(
+            with(OverflowMode::Checked, IntegerWidth::I64),
+            Ok(2_500_000_000)
+        );
+        assert_eq!(
+            with(OverflowMode::Checked, IntegerWidth::I128),
+            Ok(2_500_000_000)
+        );
+        assert_eq!(
+            with(OverflowMode::Wrapping, IntegerWidth::I32),
+            Ok(i128::from(2_500_000_000u32 as i32))
+        );
+        assert_eq!(expr.evaluate_i32(), None);
+        assert_eq!(expr.evaluate_i128(), Some(2_500_000_000));
+    }
+
+    #[test]
+    fn i128_results_keep_all_their_bits() {
+        let env = Environment::from([("big".to_string(), i128::from(i64::MAX) * 4)]);
+        let with = |input, mode| parse(input).evaluate_width_with(&env, mode, IntegerWidth::I128);
+        assert_eq!(
+            with("9223372036854775807 * 2 / 4", OverflowMode::Checked),
+            Ok(4611686018427387903)
+        );
+        assert_eq!(
+            with("9223372036854775807 + 1", OverflowMode::Checked),
+            Ok(i128::from(i64::MAX) + 1)
+        );
+        assert_eq!(
+            with("9223372036854775807 + 1", OverflowMode::Wrapping),
+            Ok(i128::from(i64::MAX) + 1)
+        );
+        assert_eq!(
+            with("big + 1", OverflowMode::Checked),
+            Ok(i128::from(i64::MAX) * 4 + 1)
+        );
+        assert_eq!(
+            with("big * big * big", OverflowMode::Checked),
+            Err(EvalError::Overflow("*"))
+        );
+        // Only a 128-bit evaluation can read a variable that large.
+        assert_eq!(
+            parse("big + 1").evaluate_with(&env),
+            Err(EvalError::Overflow("variable"))
+        );
+        assert_eq!(
+            parse("big").evaluate_width_with(&env, OverflowMode::Wrapping, IntegerWidth::I64),
+            Ok(-4)
+        );
+    }
+
+    #[test]
+    fn memo_evaluates_shared_subtrees_once() {
+        let expr = parse("(1 + 2) * 3 + (1 + 2) * 3");
+        let mut cache = HashMap::new();
+        assert_eq!(expr.evaluate_memo(&mut cache), Some(18));
+        // 1, 2, 3, `1 + 2`, `(1 + 2) * 3` and the whole sum: one entry per
+        // distinct subtree rather than one per node.
+        assert_eq!(cache.len(), 6);
+        assert_eq!(expr.node_count(), 11);
+
+        // A cached subtree is never looked at again, so a planted value
+        // shows up in both places it occurs.
+        let mut cache = HashMap::from([(parse("1 + 2"), Some(10))]);
+        assert_eq!(expr.evaluate_memo(&mut cache), Some(60));
+    }
+
+    #[test]
+    fn integer_math_functions() {
+        assert_eq!(eval("sqrt(16)"), Ok(4));
+        assert_eq!(eval("sqrt(15)"), Ok(3));
+        assert_eq!(eval("sqrt(0)"), Ok(0));
+        assert_eq!(eval("sqrt(9223372036854775807)"), Ok(3037000499));
+        assert_eq!(eval("log2(1024)"), Ok(10));
+        assert_eq!(eval("log10(999)"), Ok(2));
+        assert_eq!(eval("sqrt(-1)"), Err(EvalError::InvalidOperand("sqrt")));
+        assert_eq!(eval("log2(0)"), Err(EvalError::InvalidOperand("log2")));
+        assert_eq!(eval("log10(-5)"), Err(EvalError::InvalidOperand("log10")));
+        assert_eq!(
+            eval("sqrt(1, 2)"),
+            Err(EvalError::WrongArity {
+                name: "sqrt".to_string(),
+                expected: 1,
+                found: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn trace_is_post_order() {
+        let mut out = String::new();
+        assert_eq!(
+            parse("2 * 3 + 4").write_trace(&mut out, &Environment::new(), 0),
+            Ok(10)
+        );
+        assert_eq!(out, "    2\n    3\n  * = 6\n  4\n+ = 10\n");
+
+        let mut out = String::new();
+        let expr = parse("9223372036854775807 * 2 + 1");
+        assert_eq!(
+            expr.write_trace(&mut out, &Environment::new(), 0),
+            Err(EvalError::Overflow("*"))
+        );
+        assert_eq!(out, "    9223372036854775807\n    2\n  * = overflow\n");
+    }
+
+    type Evaluator = fn(&Expression) -> Result<i64, EvalError>;
+
+    // Every evaluator that follows the rules of `evaluate`, apart from the
+    // memoizing one, which only reports whether it failed.
+    const EVALUATORS: [(&str, Evaluator); 10] = [
+        ("evaluate", |e| e.evaluate()),
+        ("wrapping", |e| e.evaluate_mode(OverflowMode::Wrapping)),
+        ("i32", |e| {
+            e.evaluate_width_with(
+                &Environment::new(),
+                OverflowMode::Checked,
+                IntegerWidth::I32,
+            )
+            .map(|value| i64::try_from(value).unwrap())
+        }),
+        ("i128", |e| {
+            e.evaluate_width_with(
+                &Environment::new(),
+                OverflowMode::Checked,
+                IntegerWidth::I128,
+            )
+            .map(|value| i64::try_from(value).unwrap())
+        }),
+        ("exact", |e| e.evaluate_exact()),
+        ("located", |e| {
+            e.evaluate_located().map_err(|failure| failure.kind)
+        }),
+        ("bounded", |e| e.evaluate_bounded(1000)),
+        ("detailed", |e| e.evaluate_detailed().value),
+        ("trace", |e| {
+            e.write_trace(&mut String::new(), &Environment::new(), 0)
+        }),
+        ("value tree", |e| e.value_tree(&Environment::new()).value),
+    ];
+
+    #[test]
+    fn zero_factor_short_circuits_everywhere() {
+        let cases = [
+            ("0 * (9223372036854775807 * 2)", Ok(0)),
+            ("(9223372036854775807 * 2) * 0", Ok(0)),
+            ("-(56 + 0 * (88! * 35))", Ok(-56)),
+            ("0 * x", Ok(0)),
+            ("x * 0", Err(EvalError::UndefinedVariable("x".to_string()))),
+            ("(1 / 0) * 0", Err(EvalError::DivisionByZero("/"))),
+            (
+                "nosuchfn(1) * 0",
+                Err(EvalError::UnknownFunction("nosuchfn".to_string())),
+            ),
+        ];
+        for (input, expected) in cases {
+            let expr = parse(input);
+            for (name, evaluator) in EVALUATORS {
+                assert_eq!(evaluator(&expr), expected, "{} of {:?}", name, input);
+            }
+            assert_eq!(
+                expr.evaluate_memo(&mut HashMap::new()),
+                expected.clone().ok(),
+                "memo of {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn let_and_conditionals_agree_everywhere() {
+        let cases = [
+            ("let x = 3 in (let x = x + 1 in x) * x", Ok(12)),
+            ("let x = 2 in let y = x * x in y + x", Ok(6)),
+            (
+                "(let x = 2 in x) + x",
+                Err(EvalError::UndefinedVariable("x".to_string())),
+            ),
+            ("let x = 1 / 0 in 5", Err(EvalError::DivisionByZero("/"))),
+            ("1 ? 7 : 1 / 0", Ok(7)),
+            ("let x = 0 in x ? 1 / 0 : x + 1", Ok(1)),
+            // The same subtree under two bindings of `x`.
+            ("(let x = 1 in x + 1) + (let x = 2 in x + 1)", Ok(5)),
+        ];
+        for (input, expected) in cases {
+            let expr = parse(input);
+            for (name, evaluator) in EVALUATORS {
+                assert_eq!(evaluator(&expr), expected, "{} of {:?}", name, input);
+            }
+            assert_eq!(
+                expr.evaluate_memo(&mut HashMap::new()),
+                expected.clone().ok(),
+                "memo of {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn exact_division() {
+        assert_eq!(parse("6 / 2").evaluate_exact(), Ok(3));
+        assert_eq!(
+            parse("7 / 2").evaluate_exact(),
+            Err(EvalError::InexactDivision)
+        );
+        assert_eq!(parse("-7 / 7 + 1").evaluate_exact(), Ok(0));
+        assert_eq!(eval("7 / 2"), Ok(3));
+    }
+
+    #[test]
+    fn let_scoping() {
+        assert_eq!(eval("let x = 5 in x * x"), Ok(25));
+        assert_eq!(eval("let x = 1 in let x = 2 in x"), Ok(2));
+        // The inner binding ends with its body and sees the outer `x`.
+        assert_eq!(eval("let x = 3 in (let x = x + 1 in x) * x"), Ok(12));
+        assert_eq!(
+            eval("(let x = 2 in x) + x"),
+            Err(EvalError::UndefinedVariable("x".to_string()))
+        );
+
+        // A local binding shadows the environment only inside its body.
+        let env = Environment::from([("x".to_string(), 10)]);
+        assert_eq!(parse("(let x = 1 in x) + x").evaluate_with(&env), Ok(11));
+    }
+
+    #[test]
+    fn failure_carries_the_failing_subtree() {
+        let failure = parse("1 + (10 / 0)").evaluate_located().unwrap_err();
+        assert_eq!(failure.kind, EvalError::DivisionByZero("/"));
+        assert_eq!(failure.expr, parse("10 / 0"));
+        assert_eq!(
+            failure.to_string(),
+            "Division by zero in '/' while evaluating `10 / 0`"
+        );
+        let failure = parse("2 * (x + 1)").evaluate_located().unwrap_err();
+        assert_eq!(failure.expr, Expression::var("x"));
+    }
+
+    #[test]
+    fn located_failures_in_each_mode_and_width() {
+        let located = |input, mode, width| {
+            parse(input)
+                .evaluate_located_width_with(&Environment::new(), mode, width)
+                .map_err(|failure| failure.expr)
+        };
+        assert_eq!(
+            located(
+                "1 + (2147483647 + 1)",
+                OverflowMode::Checked,
+                IntegerWidth::I32
+            ),
+            Err(parse("2147483647 + 1"))
+        );
+        assert_eq!(
+            located(
+                "1 + (2147483647 + 1)",
+                OverflowMode::Wrapping,
+                IntegerWidth::I32
+            ),
+            Ok(i128::from(i32::MIN) + 1)
+        );
+        assert_eq!(
+            located(
+                "(9223372036854775807 + 1) / 0",
+                OverflowMode::Wrapping,
+                IntegerWidth::I64
+            ),
+            Err(parse("(9223372036854775807 + 1) / 0"))
+        );
+        assert_eq!(
+            located(
+                "9223372036854775807 * 4",
+                OverflowMode::Checked,
+                IntegerWidth::I128
+            ),
+            Ok(i128::from(i64::MAX) * 4)
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use crate::expression::{
+    Associativity, COMPARISON_PRECEDENCE, Expression, FACTORIAL_PRECEDENCE, NEGATION_PRECEDENCE,
+};
+
+impl Expression {
+    /// One sentence for every operator written directly inside another, in
+    /// pre-order, saying why the inner one is evaluated first: it binds
+    /// tighter, the two group from one side, or parentheses force it.
+    /// `2 + 3 * 4` gives "`*` binds tighter than `+`, so `3 * 4` is evaluated
+    /// first". Call arguments and the parts of a `let` are only explained
+    /// inside themselves.
+    pub fn explain_precedence(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        self.explain_into(&mut lines);
+        lines
+    }
+
+    fn explain_into(&self, lines: &mut Vec<String>) {
+        for (i, child) in self.children().into_iter().enumerate() {
+            if let (Some(outer), Some(inner), Some(required)) = (
+                self.operator_precedence(),
+                child.operator_precedence(),
+                self.operand_precedence(i),
+            ) {
+                lines.push(self.explain_nesting(child, outer, inner, required));
+            }
+            child.explain_into(lines);
+        }
+    }
+
+    fn explain_nesting(&self, child: &Expression, outer: u8, inner: u8, required: u8) -> String {
+        let (outer_name, inner_name) = (self.operator_name(), child.operator_name());
+        if inner < required {
+            return format!(
+                "parentheses make `{}` evaluate before {}",
+                child, outer_name
+            );
+        }
+        let reason = if inner > outer {
+            format!("{} binds tighter than {}", inner_name, outer_name)
+        } else if child.children().len() == 1 {
+            format!("{} applies from the inside out", outer_name)
+        } else {
+            // Only the side the operators group from takes the same level
+            // without parentheses.
+            let side = match self.as_binary().map(|(op, _, _)| op.associativity()) {
+                Some(Associativity::Left) => "left",
+                _ => "right",
+            };
+            if inner_name == outer_name {
+                format!("{} groups from the {}", outer_name, side)
+            } else {
+                format!(
+                    "{} and {} have the same precedence and group from the {}",
+                    inner_name, outer_name, side
+                )
+            }
+        };
+        format!("{}, so `{}` is evaluated first", reason, child)
+    }
+
+    // The level this node is written at, as in `write_prec`; `None` for
+    // leaves and calls, which never need grouping, and for `let`, which is
+    // left out like a call.
+    fn operator_precedence(&self) -> Option<u8> {
+        match self {
+            Expression::Number(_)
+            | Expression::Variable(_)
+            | Expression::Call { .. }
+            | Expression::Let { .. } => None,
+            Expression::Negation(_) => Some(NEGATION_PRECEDENCE),
+            Expression::Factorial(_) => Some(FACTORIAL_PRECEDENCE),
+            Expression::Comparison { .. } => Some(COMPARISON_PRECEDENCE),
+            Expression::Conditional { .. } => Some(0),
+            _ => self.as_binary().map(|(op, _, _)| op.precedence()),
+        }
+    }
+
+    // The level the child at `index` may have without parentheses.
+    fn operand_precedence(&self, index: usize) -> Option<u8> {
+        match self {
+            Expression::Number(_)
+            | Expression::Variable(_)
+            | Expression::Call { .. }
+            | Expression::Let { .. } => None,
+            Expression::Negation(_) => Some(NEGATION_PRECEDENCE),
+            Expression::Factorial(_) => Some(FACTORIAL_PRECEDENCE),
+            Expression::Comparison { .. } => Some(COMPARISON_PRECEDENCE + 1),
+            Expression::Conditional { .. } => Some(if index == 0 { 1 } else { 0 }),
+            _ => self.as_binary().map(|(op, _, _)| {
+                let (left, right) = op.operand_precedences();
+                if index == 0 { left } else { right }
+            }),
+        }
+    }
+
+    fn operator_name(&self) -> String {
+        match self {
+            Expression::Negation(_) => "unary `-`".to_string(),
+            Expression::Conditional { .. } => "`? :`".to_string(),
+            _ => format!("`{}`", self.label()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input)
+            .and_then(|mut parser| parser.parse())
+            .expect("test input parses")
+    }
+
+    fn explain(input: &str) -> Vec<String> {
+        parse(input).explain_precedence()
+    }
+
+    #[test]
+    fn precedence_explanations() {
+        assert_eq!(
+            explain("2 + 3 * 4"),
+            ["`*` binds tighter than `+`, so `3 * 4` is evaluated first"]
+        );
+        assert_eq!(
+            explain("(2 + 3) * 4"),
+            ["parentheses make `2 + 3` evaluate before `*`"]
+        );
+        assert_eq!(
+            explain("1 - 2 - 3"),
+            ["`-` groups from the left, so `1 - 2` is evaluated first"]
+        );
+        assert_eq!(
+            explain("2 ** 3 ** 2"),
+            ["`**` groups from the right, so `3 ** 2` is evaluated first"]
+        );
+        assert!(explain("7").is_empty());
+    }
+}
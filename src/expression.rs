@@ -0,0 +1,1910 @@
+use std::fmt;
+use std::io;
+
+use crate::eval::{Environment, EvalError, ValueTree};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Expression {
+    Number(i64),
+    Variable(String),
+    Addition(Box<Expression>, Box<Expression>),
+    Subtraction(Box<Expression>, Box<Expression>),
+    Multiplication(Box<Expression>, Box<Expression>),
+    Division(Box<Expression>, Box<Expression>),
+    Remainder(Box<Expression>, Box<Expression>),
+    Power(Box<Expression>, Box<Expression>),
+    Negation(Box<Expression>),
+    Factorial(Box<Expression>),
+    Call {
+        name: String,
+        args: Vec<Expression>,
+    },
+    /// Evaluates to 1 when the comparison holds and 0 otherwise.
+    Comparison {
+        op: CompareOp,
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    Conditional {
+        cond: Box<Expression>,
+        then: Box<Expression>,
+        els: Box<Expression>,
+    },
+    /// `let name = value in body`: `body` with `name` bound to the value of
+    /// `value`, which is evaluated outside the binding.
+    Let {
+        name: String,
+        value: Box<Expression>,
+        body: Box<Expression>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CompareOp {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+// Comparisons bind looser than arithmetic. Unary minus and postfix `!` bind
+// tighter than the other binary operators, with `**` between them, so
+// `-2 ** 2` is `-(2 ** 2)` and `2 ** 3!` is `2 ** (3!)`.
+pub(crate) const COMPARISON_PRECEDENCE: u8 = 1;
+pub(crate) const NEGATION_PRECEDENCE: u8 = 6;
+pub(crate) const FACTORIAL_PRECEDENCE: u8 = 8;
+
+impl CompareOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            CompareOp::Less => "<",
+            CompareOp::LessEqual => "<=",
+            CompareOp::Greater => ">",
+            CompareOp::GreaterEqual => ">=",
+            CompareOp::Equal => "==",
+            CompareOp::NotEqual => "!=",
+        }
+    }
+
+    pub fn holds<N: PartialOrd>(self, left: &N, right: &N) -> bool {
+        match self {
+            CompareOp::Less => left < right,
+            CompareOp::LessEqual => left <= right,
+            CompareOp::Greater => left > right,
+            CompareOp::GreaterEqual => left >= right,
+            CompareOp::Equal => left == right,
+            CompareOp::NotEqual => left != right,
+        }
+    }
+}
+
+impl BinOp {
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinOp::Add | BinOp::Sub => 2,
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 4,
+            BinOp::Pow => 7,
+        }
+    }
+
+    pub fn associativity(self) -> Associativity {
+        match self {
+            BinOp::Pow => Associativity::Right,
+            _ => Associativity::Left,
+        }
+    }
+
+    /// Precedences the left and right operands are written at. The operand on
+    /// the non-associative side is bumped by one so that `a - (b - c)` keeps
+    /// its parentheses and the parser never regroups it.
+    pub(crate) fn operand_precedences(self) -> (u8, u8) {
+        let prec = self.precedence();
+        match self.associativity() {
+            Associativity::Left => (prec, prec + 1),
+            Associativity::Right => (prec + 1, prec),
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::Pow => "**",
+        }
+    }
+
+    fn is_additive(self) -> bool {
+        matches!(self, BinOp::Add | BinOp::Sub)
+    }
+}
+
+/// Pre-order iterator over a tree: each node comes before its children, which
+/// are visited left to right. Returned by `Expression::iter`.
+#[derive(Debug, Clone)]
+pub struct Subexpressions<'a> {
+    stack: Vec<&'a Expression>,
+}
+
+impl<'a> Iterator for Subexpressions<'a> {
+    type Item = &'a Expression;
+
+    fn next(&mut self) -> Option<&'a Expression> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children().into_iter().rev());
+        Some(node)
+    }
+}
+
+impl<'a> IntoIterator for &'a Expression {
+    type Item = &'a Expression;
+    type IntoIter = Subexpressions<'a>;
+
+    fn into_iter(self) -> Subexpressions<'a> {
+        self.iter()
+    }
+}
+
+/// Wraps an integer in a `Number` leaf.
+///
+/// # Examples
+///
+/// ```
+/// use arvore_sintatica::Expression;
+///
+/// let expr: Expression = 42.into();
+/// assert_eq!(expr, Expression::num(42));
+/// assert_eq!(Expression::add(7.into(), 8.into()).evaluate(), Ok(15));
+/// ```
+impl From<i64> for Expression {
+    fn from(n: i64) -> Self {
+        Expression::Number(n)
+    }
+}
+
+// Shorthands for building trees by hand without the `Box::new` nesting. These
+// are plain constructors, not the `std::ops` methods clippy mistakes them for.
+#[allow(clippy::should_implement_trait)]
+impl Expression {
+    /// A `Number` leaf.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::num(7);
+    /// assert_eq!(expr.to_string(), "7");
+    /// assert_eq!(expr.evaluate(), Ok(7));
+    /// ```
+    pub fn num(n: i64) -> Expression {
+        Expression::Number(n)
+    }
+
+    /// A `Variable` leaf, looked up in the environment when evaluated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// use std::collections::HashMap;
+    ///
+    /// let expr = Expression::var("x");
+    /// let env = HashMap::from([("x".to_string(), 5)]);
+    /// assert_eq!(expr.to_string(), "x");
+    /// assert_eq!(expr.evaluate_with(&env), Ok(5));
+    /// ```
+    pub fn var(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+
+    /// `left + right`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::add(Expression::num(1), Expression::num(2));
+    /// assert_eq!(expr.to_string(), "1 + 2");
+    /// assert_eq!(expr.evaluate(), Ok(3));
+    /// ```
+    pub fn add(left: Expression, right: Expression) -> Expression {
+        Expression::binary(BinOp::Add, left, right)
+    }
+
+    /// `left - right`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::sub(Expression::num(10), Expression::num(4));
+    /// assert_eq!(expr.to_string(), "10 - 4");
+    /// assert_eq!(expr.evaluate(), Ok(6));
+    /// ```
+    pub fn sub(left: Expression, right: Expression) -> Expression {
+        Expression::binary(BinOp::Sub, left, right)
+    }
+
+    /// `left * right`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::mul(
+    ///     Expression::add(Expression::num(1), Expression::num(2)),
+    ///     Expression::num(3),
+    /// );
+    /// assert_eq!(expr.to_string(), "(1 + 2) * 3");
+    /// assert_eq!(expr.evaluate(), Ok(9));
+    /// ```
+    pub fn mul(left: Expression, right: Expression) -> Expression {
+        Expression::binary(BinOp::Mul, left, right)
+    }
+
+    /// `left / right`, truncating toward zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::div(Expression::num(7), Expression::num(2));
+    /// assert_eq!(expr.to_string(), "7 / 2");
+    /// assert_eq!(expr.evaluate(), Ok(3));
+    /// ```
+    pub fn div(left: Expression, right: Expression) -> Expression {
+        Expression::binary(BinOp::Div, left, right)
+    }
+
+    /// `left % right`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::rem(Expression::num(7), Expression::num(3));
+    /// assert_eq!(expr.to_string(), "7 % 3");
+    /// assert_eq!(expr.evaluate(), Ok(1));
+    /// ```
+    pub fn rem(left: Expression, right: Expression) -> Expression {
+        Expression::binary(BinOp::Rem, left, right)
+    }
+
+    /// `base ** exponent`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::pow(Expression::num(2), Expression::num(10));
+    /// assert_eq!(expr.to_string(), "2 ** 10");
+    /// assert_eq!(expr.evaluate(), Ok(1024));
+    /// ```
+    pub fn pow(base: Expression, exponent: Expression) -> Expression {
+        Expression::binary(BinOp::Pow, base, exponent)
+    }
+
+    /// `-operand`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::neg(Expression::num(5));
+    /// assert_eq!(expr.to_string(), "-5");
+    /// assert_eq!(expr.evaluate(), Ok(-5));
+    /// ```
+    pub fn neg(operand: Expression) -> Expression {
+        Expression::Negation(Box::new(operand))
+    }
+
+    /// `operand!`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::factorial(Expression::num(5));
+    /// assert_eq!(expr.to_string(), "5!");
+    /// assert_eq!(expr.evaluate(), Ok(120));
+    /// ```
+    pub fn factorial(operand: Expression) -> Expression {
+        Expression::Factorial(Box::new(operand))
+    }
+
+    /// A call to the built-in function `name`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::call("max", vec![Expression::num(3), Expression::num(8)]);
+    /// assert_eq!(expr.to_string(), "max(3, 8)");
+    /// assert_eq!(expr.evaluate(), Ok(8));
+    /// ```
+    pub fn call(name: &str, args: Vec<Expression>) -> Expression {
+        Expression::Call {
+            name: name.to_string(),
+            args,
+        }
+    }
+
+    /// `left op right`, which evaluates to 1 when it holds and 0 otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::{CompareOp, Expression};
+    ///
+    /// let expr = Expression::compare(CompareOp::Less, Expression::num(1), Expression::num(2));
+    /// assert_eq!(expr.to_string(), "1 < 2");
+    /// assert_eq!(expr.evaluate(), Ok(1));
+    /// ```
+    pub fn compare(op: CompareOp, left: Expression, right: Expression) -> Expression {
+        Expression::Comparison {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    /// `cond ? then : els`; only the chosen branch is evaluated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::conditional(
+    ///     Expression::num(0),
+    ///     Expression::div(Expression::num(1), Expression::num(0)),
+    ///     Expression::num(2),
+    /// );
+    /// assert_eq!(expr.to_string(), "0 ? 1 / 0 : 2");
+    /// assert_eq!(expr.evaluate(), Ok(2));
+    /// ```
+    pub fn conditional(cond: Expression, then: Expression, els: Expression) -> Expression {
+        Expression::Conditional {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            els: Box::new(els),
+        }
+    }
+
+    /// `let name = value in body`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use arvore_sintatica::Expression;
+    ///
+    /// let expr = Expression::let_in(
+    ///     "x",
+    ///     Expression::num(3),
+    ///     Expression::mul(Expression::var("x"), Expression::var("x")),
+    /// );
+    /// assert_eq!(expr.to_string(), "let x = 3 in x * x");
+    /// assert_eq!(expr.evaluate(), Ok(9));
+    /// ```
+    pub fn let_in(name: &str, value: Expression, body: Expression) -> Expression {
+        Expression::Let {
+            name: name.to_string(),
+            value: Box::new(value),
+            body: Box::new(body),
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_prec(f, 0, &FormatStyle::default())
+    }
+}
+
+const PLOT_HEIGHT: usize = 10;
+
+struct TreeGlyphs {
+    branch: &'static str,
+    last: &'static str,
+    pipe: &'static str,
+    blank: &'static str,
+    ellipsis: &'static str,
+}
+
+const UNICODE_TREE: TreeGlyphs = TreeGlyphs {
+    branch: "├",
+    last: "└",
+    pipe: "│ ",
+    blank: "  ",
+    ellipsis: "…",
+};
+
+const ASCII_TREE: TreeGlyphs = TreeGlyphs {
+    branch: "|--",
+    last: "`--",
+    pipe: "|   ",
+    blank: "    ",
+    ellipsis: "...",
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TreeStyle {
+    pub ascii: bool,
+    /// Wraps operator labels in cyan and numbers in yellow ANSI escapes.
+    pub color: bool,
+    /// Children below this depth (the root is depth 0) are replaced by a
+    /// single `…` line.
+    pub max_depth: Option<usize>,
+    /// Stops after this many nodes, marking the rest with `…` lines.
+    pub max_nodes: Option<usize>,
+    /// Follows every node but a literal with its value, or with `[overflow]`,
+    /// `[div0]` or `[error]` when its subtree fails to evaluate.
+    pub annotate: bool,
+    /// Operator symbols for the node labels.
+    pub symbols: FormatStyle,
+}
+
+/// The symbols the arithmetic operators are printed with, for example `×`
+/// and `÷` in place of `*` and `/`. Comparisons and `? :` always keep their
+/// own. The default is the ASCII the parser reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatStyle {
+    pub add: &'static str,
+    pub sub: &'static str,
+    pub mul: &'static str,
+    pub div: &'static str,
+    pub rem: &'static str,
+    pub pow: &'static str,
+    /// Unary minus, also used for the sign of negative literals.
+    pub neg: &'static str,
+    pub factorial: &'static str,
+}
+
+impl Default for FormatStyle {
+    fn default() -> Self {
+        FormatStyle {
+            add: "+",
+            sub: "-",
+            mul: "*",
+            div: "/",
+            rem: "%",
+            pow: "**",
+            neg: "-",
+            factorial: "!",
+        }
+    }
+}
+
+impl FormatStyle {
+    pub fn binary_symbol(&self, op: BinOp) -> &'static str {
+        match op {
+            BinOp::Add => self.add,
+            BinOp::Sub => self.sub,
+            BinOp::Mul => self.mul,
+            BinOp::Div => self.div,
+            BinOp::Rem => self.rem,
+            BinOp::Pow => self.pow,
+        }
+    }
+}
+
+// A single letter is already set in italics; a longer name would otherwise
+// read as a product of single-letter variables.
+fn write_latex_name(out: &mut String, name: &str) {
+    if name.chars().count() == 1 {
+        out.push_str(name);
+    } else {
+        out.push_str("\\mathit{");
+        out.push_str(name);
+        out.push('}');
+    }
+}
+
+const OPERATOR_COLOR: &str = "\x1b[36m";
+const NUMBER_COLOR: &str = "\x1b[33m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+impl Expression {
+    pub fn potential_undefined(&self) -> Vec<String> {
+        let mut found = Vec::new();
+        self.collect_potential_undefined(&mut found);
+        found
+    }
+
+    fn collect_potential_undefined(&self, found: &mut Vec<String>) {
+        if let Expression::Division(_, divisor) | Expression::Remainder(_, divisor) = self
+            && !divisor.is_provably_nonzero()
+        {
+            found.push(self.to_string());
+        }
+        for child in self.children() {
+            child.collect_potential_undefined(found);
+        }
+    }
+
+    pub fn may_divide_by_zero(&self) -> bool {
+        if let Expression::Division(_, divisor) | Expression::Remainder(_, divisor) = self
+            && !divisor.is_provably_nonzero()
+        {
+            return true;
+        }
+        self.children()
+            .into_iter()
+            .any(|child| child.may_divide_by_zero())
+    }
+
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Expression::Variable(_) => false,
+            // Any value will do for the bound name.
+            Expression::Let { name, value, body } => {
+                value.is_constant() && body.substitute(name, 0).is_constant()
+            }
+            _ => self.children().into_iter().all(|child| child.is_constant()),
+        }
+    }
+
+    fn is_provably_nonzero(&self) -> bool {
+        matches!(self.evaluate_with(&Environment::new()), Ok(v) if v != 0)
+    }
+
+    pub fn as_binary(&self) -> Option<(BinOp, &Expression, &Expression)> {
+        match self {
+            Expression::Addition(left, right) => Some((BinOp::Add, left, right)),
+            Expression::Subtraction(left, right) => Some((BinOp::Sub, left, right)),
+            Expression::Multiplication(left, right) => Some((BinOp::Mul, left, right)),
+            Expression::Division(left, right) => Some((BinOp::Div, left, right)),
+            Expression::Remainder(left, right) => Some((BinOp::Rem, left, right)),
+            Expression::Power(left, right) => Some((BinOp::Pow, left, right)),
+            _ => None,
+        }
+    }
+
+    pub fn binary(op: BinOp, left: Expression, right: Expression) -> Expression {
+        let (left, right) = (Box::new(left), Box::new(right));
+        match op {
+            BinOp::Add => Expression::Addition(left, right),
+            BinOp::Sub => Expression::Subtraction(left, right),
+            BinOp::Mul => Expression::Multiplication(left, right),
+            BinOp::Div => Expression::Division(left, right),
+            BinOp::Rem => Expression::Remainder(left, right),
+            BinOp::Pow => Expression::Power(left, right),
+        }
+    }
+
+    pub fn linearize_chain(&self) -> Option<(Expression, Vec<(BinOp, Expression)>)> {
+        let (root_op, _, _) = self.as_binary()?;
+
+        let mut pairs = Vec::new();
+        let mut node = self;
+        while let Some((op, left, right)) = node.as_binary()
+            && op.is_additive() == root_op.is_additive()
+        {
+            pairs.push((op, right.clone()));
+            node = left;
+        }
+        pairs.reverse();
+
+        Some((node.clone(), pairs))
+    }
+
+    pub fn children(&self) -> Vec<&Expression> {
+        match self {
+            Expression::Number(_) | Expression::Variable(_) => vec![],
+            Expression::Addition(left, right)
+            | Expression::Subtraction(left, right)
+            | Expression::Multiplication(left, right)
+            | Expression::Division(left, right)
+            | Expression::Remainder(left, right)
+            | Expression::Power(left, right)
+            | Expression::Comparison { left, right, .. } => vec![left, right],
+            Expression::Negation(expr) | Expression::Factorial(expr) => vec![expr],
+            Expression::Call { args, .. } => args.iter().collect(),
+            Expression::Conditional { cond, then, els } => vec![cond, then, els],
+            Expression::Let { value, body, .. } => vec![value, body],
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Expression::Number(_) | Expression::Variable(_) => vec![],
+            Expression::Addition(left, right)
+            | Expression::Subtraction(left, right)
+            | Expression::Multiplication(left, right)
+            | Expression::Division(left, right)
+            | Expression::Remainder(left, right)
+            | Expression::Power(left, right)
+            | Expression::Comparison { left, right, .. } => vec![left, right],
+            Expression::Negation(expr) | Expression::Factorial(expr) => vec![expr],
+            Expression::Call { args, .. } => args.iter_mut().collect(),
+            Expression::Conditional { cond, then, els } => vec![cond, then, els],
+            Expression::Let { value, body, .. } => vec![value, body],
+        }
+    }
+
+    /// Rebuilds this node with every direct child replaced by `f(child)`.
+    pub(crate) fn map_children(&self, mut f: impl FnMut(&Expression) -> Expression) -> Expression {
+        let mut g = |e: &Expression| Box::new(f(e));
+        match self {
+            Expression::Number(_) | Expression::Variable(_) => self.clone(),
+            Expression::Addition(l, r) => Expression::Addition(g(l), g(r)),
+            Expression::Subtraction(l, r) => Expression::Subtraction(g(l), g(r)),
+            Expression::Multiplication(l, r) => Expression::Multiplication(g(l), g(r)),
+            Expression::Division(l, r) => Expression::Division(g(l), g(r)),
+            Expression::Remainder(l, r) => Expression::Remainder(g(l), g(r)),
+            Expression::Power(l, r) => Expression::Power(g(l), g(r)),
+            Expression::Negation(e) => Expression::Negation(g(e)),
+            Expression::Factorial(e) => Expression::Factorial(g(e)),
+            Expression::Call { name, args } => Expression::Call {
+                name: name.clone(),
+                args: args.iter().map(f).collect(),
+            },
+            Expression::Comparison { op, left, right } => Expression::Comparison {
+                op: *op,
+                left: g(left),
+                right: g(right),
+            },
+            Expression::Conditional { cond, then, els } => Expression::Conditional {
+                cond: g(cond),
+                then: g(then),
+                els: g(els),
+            },
+            Expression::Let { name, value, body } => Expression::Let {
+                name: name.clone(),
+                value: g(value),
+                body: g(body),
+            },
+        }
+    }
+
+    pub fn map_numbers<F: Fn(i64) -> i64>(&self, f: F) -> Expression {
+        self.map_numbers_with(&f)
+    }
+
+    fn map_numbers_with(&self, f: &impl Fn(i64) -> i64) -> Expression {
+        match self {
+            Expression::Number(n) => Expression::Number(f(*n)),
+            _ => self.map_children(|child| child.map_numbers_with(f)),
+        }
+    }
+
+    /// Replaces every `Variable(name)` with `Number(value)`, leaving other
+    /// variables alone, as well as a `let` body that binds `name` again.
+    /// Follow with `simplify` for partial evaluation.
+    pub fn substitute(&self, name: &str, value: i64) -> Expression {
+        match self {
+            Expression::Variable(var) if var == name => Expression::Number(value),
+            Expression::Let {
+                name: bound,
+                value: bound_value,
+                body,
+            } if bound == name => Expression::Let {
+                name: bound.clone(),
+                value: Box::new(bound_value.substitute(name, value)),
+                body: body.clone(),
+            },
+            _ => self.map_children(|child| child.substitute(name, value)),
+        }
+    }
+
+    pub fn iter(&self) -> Subexpressions<'_> {
+        Subexpressions { stack: vec![self] }
+    }
+
+    /// Bottom-up fold: `f` sees every node together with the results already
+    /// computed for its children, in `children()` order. One closure rather
+    /// than one per node kind, since the tree has far more kinds than numbers,
+    /// binary operators and negation.
+    pub fn fold<T>(&self, f: &mut impl FnMut(&Expression, Vec<T>) -> T) -> T {
+        let values = self
+            .children()
+            .into_iter()
+            .map(|child| child.fold(f))
+            .collect();
+        f(self, values)
+    }
+
+    /// Applies `f` to every node in place, in post-order: a node's children
+    /// have already been rewritten when `f` sees it.
+    pub fn walk_mut(&mut self, f: &mut impl FnMut(&mut Expression)) {
+        for child in self.children_mut() {
+            child.walk_mut(f);
+        }
+        f(self);
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.fold(&mut |_, children: Vec<usize>| 1 + children.iter().sum::<usize>())
+    }
+
+    /// The values of the number literals, in pre-order. The parser reads `-5`
+    /// as a negation of `5`; `simplify` folds it into the literal `-5`.
+    pub fn literals(&self) -> Vec<i64> {
+        self.iter()
+            .filter_map(|node| match node {
+                Expression::Number(n) => Some(*n),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Every operator in the tree, in pre-order and with repetitions, so a
+    /// caller can build a histogram. Binary operators and comparisons use
+    /// their symbol; negation is `"neg"`, calls are `"call"`, conditionals
+    /// `"?:"` and bindings `"let"`.
+    pub fn operators(&self) -> Vec<&'static str> {
+        let mut operators = Vec::new();
+        self.collect_operators(&mut operators);
+        operators
+    }
+
+    fn collect_operators(&self, operators: &mut Vec<&'static str>) {
+        let operator = match self {
+            Expression::Number(_) | Expression::Variable(_) => None,
+            Expression::Negation(_) => Some("neg"),
+            Expression::Factorial(_) => Some("!"),
+            Expression::Call { .. } => Some("call"),
+            Expression::Comparison { op, .. } => Some(op.symbol()),
+            Expression::Conditional { .. } => Some("?:"),
+            Expression::Let { .. } => Some("let"),
+            _ => self.as_binary().map(|(op, _, _)| op.symbol()),
+        };
+        operators.extend(operator);
+        for child in self.children() {
+            child.collect_operators(operators);
+        }
+    }
+
+    pub fn sample(
+        &self,
+        var: &str,
+        start: i64,
+        end: i64,
+        step: i64,
+    ) -> Vec<(i64, Result<i64, EvalError>)> {
+        let mut samples = Vec::new();
+        if step <= 0 {
+            return samples;
+        }
+
+        let mut env = Environment::new();
+        let mut x = start;
+        while x <= end {
+            env.insert(var.to_string(), x.into());
+            samples.push((x, self.evaluate_with(&env)));
+            match x.checked_add(step) {
+                Some(next) => x = next,
+                None => break,
+            }
+        }
+
+        samples
+    }
+
+    pub fn ascii_plot(&self, var: &str, start: i64, end: i64) -> String {
+        let samples = self.sample(var, start, end, 1);
+        let values: Vec<i64> = samples
+            .iter()
+            .filter_map(|(_, v)| v.as_ref().ok().copied())
+            .collect();
+        let (min, max) = match (values.iter().min(), values.iter().max()) {
+            (Some(&min), Some(&max)) => (min, max),
+            _ => return String::new(),
+        };
+
+        let mut grid = vec![vec![' '; samples.len()]; PLOT_HEIGHT];
+        for (col, (_, value)) in samples.iter().enumerate() {
+            if let Ok(v) = value {
+                let row = if max == min {
+                    0
+                } else {
+                    ((*v as i128 - min as i128) * (PLOT_HEIGHT as i128 - 1)
+                        / (max as i128 - min as i128)) as usize
+                };
+                grid[PLOT_HEIGHT - 1 - row][col] = '*';
+            }
+        }
+
+        grid.iter()
+            .map(|row| row.iter().collect::<String>().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn print(&self) {
+        print!("{}", self);
+    }
+
+    pub fn write_expr<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        self.write_prec(w, 0, &FormatStyle::default())
+    }
+
+    pub fn write_expr_styled<W: fmt::Write>(&self, w: &mut W, style: &FormatStyle) -> fmt::Result {
+        self.write_prec(w, 0, style)
+    }
+
+    /// Like `to_string`, with the operator symbols of `style`.
+    pub fn to_string_styled(&self, style: &FormatStyle) -> String {
+        let mut out = String::new();
+        self.write_prec(&mut out, 0, style)
+            .expect("writing to a String cannot fail");
+        out
+    }
+
+    /// Wraps every operator in its own parentheses, as in `(2 + (3 * 4))`,
+    /// so the tree's shape can be read off without knowing any precedence.
+    /// Variables, calls and non-negative literals stay bare, and a negative
+    /// literal is written `(-5)`.
+    pub fn to_string_full_parens(&self) -> String {
+        let mut out = String::new();
+        self.write_full_parens(&mut out)
+            .expect("writing to a String cannot fail");
+        out
+    }
+
+    fn write_full_parens<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        match self {
+            Expression::Number(n) if *n < 0 => return write!(w, "({})", n),
+            Expression::Number(n) => return write!(w, "{}", n),
+            Expression::Variable(name) => return write!(w, "{}", name),
+            Expression::Call { name, args } => {
+                write!(w, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(w, ", ")?;
+                    }
+                    arg.write_full_parens(w)?;
+                }
+                return write!(w, ")");
+            }
+            _ => {}
+        }
+        write!(w, "(")?;
+        match self {
+            Expression::Negation(expr) => {
+                write!(w, "-")?;
+                expr.write_full_parens(w)?;
+            }
+            Expression::Factorial(expr) => {
+                expr.write_full_parens(w)?;
+                write!(w, "!")?;
+            }
+            Expression::Comparison { op, left, right } => {
+                left.write_full_parens(w)?;
+                write!(w, " {} ", op.symbol())?;
+                right.write_full_parens(w)?;
+            }
+            Expression::Conditional { cond, then, els } => {
+                cond.write_full_parens(w)?;
+                write!(w, " ? ")?;
+                then.write_full_parens(w)?;
+                write!(w, " : ")?;
+                els.write_full_parens(w)?;
+            }
+            Expression::Let { name, value, body } => {
+                write!(w, "let {} = ", name)?;
+                value.write_full_parens(w)?;
+                write!(w, " in ")?;
+                body.write_full_parens(w)?;
+            }
+            _ => {
+                let (op, left, right) = self.as_binary().expect("every other variant is binary");
+                left.write_full_parens(w)?;
+                write!(w, " {} ", op.symbol())?;
+                right.write_full_parens(w)?;
+            }
+        }
+        write!(w, ")")
+    }
+
+    // Precedence levels, loosest first (see `BinOp::precedence`). A node is
+    // parenthesized when the level its parent asks for is higher than its own.
+    //
+    //   0  `? :`     right-assoc: condition at 1, both branches at 0
+    //      `let`     value and body at 0; the body extends as far right as
+    //                it can, so a `let` anywhere but last is parenthesized
+    //   1  `<` `==` ... non-assoc: both operands at 2, so `(a < b) < c`
+    //                keeps its parens
+    //   2  `+` `-`   left-assoc: left operand at 2, right operand at 3
+    //   4  `*` `/` `%` left-assoc: left operand at 4, right operand at 5
+    //   6  unary `-` operand at 6, so `-(2 * 3)` keeps its parens
+    //   7  `**`      right-assoc: base at 8, exponent at 7
+    //   8  postfix `!` operand at 8, so `(-2)!` keeps its parens
+    //
+    // Negative number literals print with a leading `-` and therefore sit at
+    // level 6 like a negation. Calls bracket their own arguments and never
+    // need parens.
+    fn write_prec<W: fmt::Write>(
+        &self,
+        w: &mut W,
+        parent_prec: u8,
+        style: &FormatStyle,
+    ) -> fmt::Result {
+        match self {
+            Expression::Number(n) if *n < 0 && parent_prec > NEGATION_PRECEDENCE => {
+                write!(w, "({}{})", style.neg, n.unsigned_abs())
+            }
+            Expression::Number(n) if *n < 0 => write!(w, "{}{}", style.neg, n.unsigned_abs()),
+            Expression::Number(n) => write!(w, "{}", n),
+            Expression::Variable(name) => write!(w, "{}", name),
+            Expression::Factorial(expr) => {
+                expr.write_prec(w, FACTORIAL_PRECEDENCE, style)?;
+                write!(w, "{}", style.factorial)
+            }
+            Expression::Comparison { op, left, right } => {
+                if parent_prec > COMPARISON_PRECEDENCE {
+                    write!(w, "(")?;
+                }
+                left.write_prec(w, COMPARISON_PRECEDENCE + 1, style)?;
+                write!(w, " {} ", op.symbol())?;
+                right.write_prec(w, COMPARISON_PRECEDENCE + 1, style)?;
+                if parent_prec > COMPARISON_PRECEDENCE {
+                    write!(w, ")")?;
+                }
+                Ok(())
+            }
+            Expression::Conditional { cond, then, els } => {
+                if parent_prec > 0 {
+                    write!(w, "(")?;
+                }
+                cond.write_prec(w, 1, style)?;
+                write!(w, " ? ")?;
+                then.write_prec(w, 0, style)?;
+                write!(w, " : ")?;
+                els.write_prec(w, 0, style)?;
+                if parent_prec > 0 {
+                    write!(w, ")")?;
+                }
+                Ok(())
+            }
+            Expression::Let { name, value, body } => {
+                if parent_prec > 0 {
+                    write!(w, "(")?;
+                }
+                write!(w, "let {} = ", name)?;
+                value.write_prec(w, 0, style)?;
+                write!(w, " in ")?;
+                body.write_prec(w, 0, style)?;
+                if parent_prec > 0 {
+                    write!(w, ")")?;
+                }
+                Ok(())
+            }
+            Expression::Call { name, args } => {
+                write!(w, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(w, ", ")?;
+                    }
+                    arg.write_prec(w, 0, style)?;
+                }
+                write!(w, ")")
+            }
+            Expression::Negation(expr) => {
+                if parent_prec > NEGATION_PRECEDENCE {
+                    write!(w, "(")?;
+                }
+                write!(w, "{}", style.neg)?;
+                expr.write_prec(w, NEGATION_PRECEDENCE, style)?;
+                if parent_prec > NEGATION_PRECEDENCE {
+                    write!(w, ")")?;
+                }
+                Ok(())
+            }
+            Expression::Multiplication(left, right) => {
+                Self::write_binary(w, parent_prec, style, BinOp::Mul, left, right)
+            }
+            Expression::Division(left, right) => {
+                Self::write_binary(w, parent_prec, style, BinOp::Div, left, right)
+            }
+            Expression::Remainder(left, right) => {
+                Self::write_binary(w, parent_prec, style, BinOp::Rem, left, right)
+            }
+            Expression::Power(left, right) => {
+                Self::write_binary(w, parent_prec, style, BinOp::Pow, left, right)
+            }
+            // `a + -5` prints as `a - 5` and `a - -5` as `a + 5`.
+            Expression::Addition(left, right) => match right.negated_literal() {
+                Some(n) => Self::write_binary(
+                    w,
+                    parent_prec,
+                    style,
+                    BinOp::Sub,
+                    left,
+                    &Expression::Number(n),
+                ),
+                None => Self::write_binary(w, parent_prec, style, BinOp::Add, left, right),
+            },
+            Expression::Subtraction(left, right) => match right.negated_literal() {
+                Some(n) => Self::write_binary(
+                    w,
+                    parent_prec,
+                    style,
+                    BinOp::Add,
+                    left,
+                    &Expression::Number(n),
+                ),
+                None => Self::write_binary(w, parent_prec, style, BinOp::Sub, left, right),
+            },
+        }
+    }
+
+    // The magnitude of a negative literal, written either as `-5` or as a
+    // negation of `5`. Limited to literals so the swapped operator can never
+    // hide or introduce an overflow on `i64::MIN`.
+    fn negated_literal(&self) -> Option<i64> {
+        match self {
+            Expression::Number(n) if *n < 0 => n.checked_neg(),
+            Expression::Negation(inner) => match **inner {
+                Expression::Number(n) if n > 0 => Some(n),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn write_binary<W: fmt::Write>(
+        w: &mut W,
+        parent_prec: u8,
+        style: &FormatStyle,
+        op: BinOp,
+        left: &Expression,
+        right: &Expression,
+    ) -> fmt::Result {
+        let prec = op.precedence();
+        let (left_prec, right_prec) = op.operand_precedences();
+        if parent_prec > prec {
+            write!(w, "(")?;
+        }
+        left.write_prec(w, left_prec, style)?;
+        write!(w, " {} ", style.binary_symbol(op))?;
+        right.write_prec(w, right_prec, style)?;
+        if parent_prec > prec {
+            write!(w, ")")?;
+        }
+        Ok(())
+    }
+
+    pub fn to_latex(&self) -> String {
+        let mut out = String::new();
+        self.write_latex(&mut out, 0);
+        out
+    }
+
+    // Same levels as `write_prec`, except that `\frac` groups its operands
+    // itself and binds like a negation, so only a factorial has to wrap it.
+    fn write_latex(&self, out: &mut String, parent_prec: u8) {
+        let prec = match self {
+            Expression::Number(n) if *n < 0 => NEGATION_PRECEDENCE,
+            Expression::Number(n) => return out.push_str(&n.to_string()),
+            Expression::Addition(..) => BinOp::Add.precedence(),
+            Expression::Subtraction(..) => BinOp::Sub.precedence(),
+            Expression::Multiplication(..) => BinOp::Mul.precedence(),
+            Expression::Remainder(..) => BinOp::Rem.precedence(),
+            Expression::Power(..) => BinOp::Pow.precedence(),
+            Expression::Comparison { .. } => COMPARISON_PRECEDENCE,
+            Expression::Negation(_) | Expression::Division(..) => NEGATION_PRECEDENCE,
+            Expression::Factorial(_) => FACTORIAL_PRECEDENCE,
+            Expression::Variable(_) | Expression::Call { .. } | Expression::Conditional { .. } => {
+                FACTORIAL_PRECEDENCE + 1
+            }
+            Expression::Let { .. } => 0,
+        };
+        if parent_prec > prec {
+            out.push('(');
+        }
+        match self {
+            Expression::Addition(left, right) => {
+                Self::write_latex_binary(out, BinOp::Add, left, "+", right)
+            }
+            Expression::Subtraction(left, right) => {
+                Self::write_latex_binary(out, BinOp::Sub, left, "-", right)
+            }
+            Expression::Multiplication(left, right) => {
+                Self::write_latex_binary(out, BinOp::Mul, left, " \\cdot ", right)
+            }
+            Expression::Remainder(left, right) => {
+                Self::write_latex_binary(out, BinOp::Rem, left, " \\bmod ", right)
+            }
+            // The exponent is grouped by its braces, so only the base can
+            // need parentheses.
+            Expression::Power(base, exponent) => {
+                base.write_latex(out, BinOp::Pow.precedence() + 1);
+                out.push_str("^{");
+                exponent.write_latex(out, 0);
+                out.push('}');
+            }
+            Expression::Comparison { op, left, right } => {
+                left.write_latex(out, COMPARISON_PRECEDENCE + 1);
+                out.push_str(match op {
+                    CompareOp::Less => " < ",
+                    CompareOp::LessEqual => " \\leq ",
+                    CompareOp::Greater => " > ",
+                    CompareOp::GreaterEqual => " \\geq ",
+                    CompareOp::Equal => " = ",
+                    CompareOp::NotEqual => " \\neq ",
+                });
+                right.write_latex(out, COMPARISON_PRECEDENCE + 1);
+            }
+            Expression::Division(left, right) => {
+                out.push_str("\\frac{");
+                left.write_latex(out, 0);
+                out.push_str("}{");
+                right.write_latex(out, 0);
+                out.push('}');
+            }
+            Expression::Number(n) => out.push_str(&n.to_string()),
+            Expression::Negation(expr) => {
+                out.push('-');
+                expr.write_latex(out, NEGATION_PRECEDENCE);
+            }
+            Expression::Factorial(expr) => {
+                expr.write_latex(out, FACTORIAL_PRECEDENCE);
+                out.push('!');
+            }
+            Expression::Call { name, args } if name == "abs" && args.len() == 1 => {
+                out.push_str("\\left|");
+                args[0].write_latex(out, 0);
+                out.push_str("\\right|");
+            }
+            Expression::Call { name, args } => {
+                match name.as_str() {
+                    "min" | "max" | "gcd" => out.push_str(&format!("\\{}(", name)),
+                    _ => out.push_str(&format!("\\operatorname{{{}}}(", name)),
+                }
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    arg.write_latex(out, 0);
+                }
+                out.push(')');
+            }
+            Expression::Conditional { cond, then, els } => {
+                out.push_str("\\begin{cases} ");
+                then.write_latex(out, 0);
+                out.push_str(" & \\text{if } ");
+                cond.write_latex(out, 0);
+                // A comparison already reads as a condition.
+                if !matches!(**cond, Expression::Comparison { .. }) {
+                    out.push_str(" \\neq 0");
+                }
+                out.push_str(" \\\\ ");
+                els.write_latex(out, 0);
+                out.push_str(" & \\text{otherwise} \\end{cases}");
+            }
+            Expression::Let { name, value, body } => {
+                out.push_str("\\mathbf{let}\\ ");
+                write_latex_name(out, name);
+                out.push_str(" = ");
+                value.write_latex(out, 0);
+                out.push_str("\\ \\mathbf{in}\\ ");
+                body.write_latex(out, 0);
+            }
+            Expression::Variable(name) => write_latex_name(out, name),
+        }
+        if parent_prec > prec {
+            out.push(')');
+        }
+    }
+
+    fn write_latex_binary(
+        out: &mut String,
+        op: BinOp,
+        left: &Expression,
+        separator: &str,
+        right: &Expression,
+    ) {
+        let (left_prec, right_prec) = op.operand_precedences();
+        left.write_latex(out, left_prec);
+        out.push_str(separator);
+        right.write_latex(out, right_prec);
+    }
+
+    pub fn print_tree(&self) {
+        self.write_tree(&mut io::stdout().lock())
+            .expect("failed to write to stdout");
+    }
+
+    pub fn print_tree_ascii(&self) {
+        self.write_tree_ascii(&mut io::stdout().lock())
+            .expect("failed to write to stdout");
+    }
+
+    pub fn print_tree_styled(&self, style: TreeStyle) {
+        self.write_tree_styled(&mut io::stdout().lock(), style)
+            .expect("failed to write to stdout");
+    }
+
+    pub fn print_tree_limited(&self, max_depth: usize) {
+        self.print_tree_styled(TreeStyle {
+            max_depth: Some(max_depth),
+            ..TreeStyle::default()
+        });
+    }
+
+    pub fn print_tree_budget(&self, max_nodes: usize) {
+        self.print_tree_styled(TreeStyle {
+            max_nodes: Some(max_nodes),
+            ..TreeStyle::default()
+        });
+    }
+
+    pub fn print_tree_annotated(&self) {
+        self.print_tree_styled(TreeStyle {
+            annotate: true,
+            ..TreeStyle::default()
+        });
+    }
+
+    pub fn write_tree<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_tree_styled(w, TreeStyle::default())
+    }
+
+    pub fn write_tree_ascii<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_tree_styled(
+            w,
+            TreeStyle {
+                ascii: true,
+                ..TreeStyle::default()
+            },
+        )
+    }
+
+    pub fn write_tree_styled<W: io::Write>(&self, w: &mut W, style: TreeStyle) -> io::Result<()> {
+        let glyphs = if style.ascii {
+            &ASCII_TREE
+        } else {
+            &UNICODE_TREE
+        };
+        let mut budget = style.max_nodes.unwrap_or(usize::MAX);
+        let values = style.annotate.then(|| self.value_tree(&Environment::new()));
+        self.write_tree_recursive(w, "", true, glyphs, &style, values.as_ref(), 0, &mut budget)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn write_tree_recursive<W: io::Write>(
+        &self,
+        w: &mut W,
+        prefix: &str,
+        is_last: bool,
+        glyphs: &TreeGlyphs,
+        style: &TreeStyle,
+        values: Option<&ValueTree>,
+        depth: usize,
+        budget: &mut usize,
+    ) -> io::Result<()> {
+        let current_symbol = if is_last { glyphs.last } else { glyphs.branch };
+        let child_prefix = if is_last { glyphs.blank } else { glyphs.pipe };
+        *budget = budget.saturating_sub(1);
+
+        let label = self.label_with(&style.symbols);
+        let label = match self {
+            _ if !style.color => label,
+            Expression::Number(_) => format!("{}{}{}", NUMBER_COLOR, label, RESET_COLOR),
+            Expression::Variable(_) => label,
+            _ => format!("{}{}{}", OPERATOR_COLOR, label, RESET_COLOR),
+        };
+        let label = match (self, values) {
+            (Expression::Number(_), _) | (_, None) => label,
+            (_, Some(values)) => match &values.value {
+                Ok(value) => format!("{} [{}]", label, value),
+                Err(EvalError::Overflow(_)) => format!("{} [overflow]", label),
+                Err(EvalError::DivisionByZero(_)) => format!("{} [div0]", label),
+                Err(_) => format!("{} [error]", label),
+            },
+        };
+        if !prefix.is_empty() {
+            writeln!(w, "{}{} {}", prefix, current_symbol, label)?;
+        } else {
+            writeln!(w, "{}", label)?;
+        }
+
+        let children = self.children();
+        if children.is_empty() {
+            return Ok(());
+        }
+        let child_prefix = format!("{}{}", prefix, child_prefix);
+        if style.max_depth.is_some_and(|max| depth >= max) {
+            return writeln!(w, "{}{} {}", child_prefix, glyphs.last, glyphs.ellipsis);
+        }
+        for (i, child) in children.iter().enumerate() {
+            if *budget == 0 {
+                return writeln!(w, "{}{} {}", child_prefix, glyphs.last, glyphs.ellipsis);
+            }
+            let is_last = i == children.len() - 1;
+            child.write_tree_recursive(
+                w,
+                &child_prefix,
+                is_last,
+                glyphs,
+                style,
+                values.map(|values| &values.children[i]),
+                depth + 1,
+                budget,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn to_sexpr(&self) -> String {
+        if let Expression::Number(_) | Expression::Variable(_) = self {
+            return self.label();
+        }
+        let mut out = format!("({}", self.label());
+        for child in self.children() {
+            out.push(' ');
+            out.push_str(&child.to_sexpr());
+        }
+        out.push(')');
+        out
+    }
+
+    pub fn to_dot(&self) -> String {
+        self.dot_graph(false)
+    }
+
+    pub fn to_dot_annotated(&self) -> String {
+        self.dot_graph(true)
+    }
+
+    fn dot_graph(&self, annotated: bool) -> String {
+        let mut out = String::from("digraph Expression {\n");
+        let values = annotated.then(|| self.value_tree(&Environment::new()));
+        self.write_dot_node(&mut out, &mut 0, values.as_ref());
+        out.push_str("}\n");
+        out
+    }
+
+    fn write_dot_node(
+        &self,
+        out: &mut String,
+        next_id: &mut usize,
+        values: Option<&ValueTree>,
+    ) -> usize {
+        use std::fmt::Write;
+
+        let id = *next_id;
+        *next_id += 1;
+        // Subtrees that fail to evaluate are left unannotated.
+        let tooltip = match values.map(|values| &values.value) {
+            Some(Ok(value)) => format!(", tooltip=\"{}\"", value),
+            _ => String::new(),
+        };
+        writeln!(out, "    n{} [label=\"{}\"{}];", id, self.label(), tooltip).unwrap();
+        for (i, child) in self.children().into_iter().enumerate() {
+            let child_id =
+                child.write_dot_node(out, next_id, values.map(|values| &values.children[i]));
+            writeln!(out, "    n{} -> n{};", id, child_id).unwrap();
+        }
+        id
+    }
+
+    pub(crate) fn label(&self) -> String {
+        self.label_with(&FormatStyle::default())
+    }
+
+    fn label_with(&self, symbols: &FormatStyle) -> String {
+        match self {
+            Expression::Number(n) if *n < 0 => format!("{}{}", symbols.neg, n.unsigned_abs()),
+            Expression::Number(n) => n.to_string(),
+            Expression::Variable(name) => name.clone(),
+            Expression::Negation(_) => symbols.neg.to_string(),
+            Expression::Factorial(_) => symbols.factorial.to_string(),
+            Expression::Call { name, .. } => name.clone(),
+            Expression::Comparison { op, .. } => op.symbol().to_string(),
+            Expression::Conditional { .. } => "?".to_string(),
+            Expression::Let { name, .. } => format!("let {}", name),
+            _ => {
+                let (op, _, _) = self.as_binary().expect("every other variant is binary");
+                symbols.binary_symbol(op).to_string()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_squares_over_a_range() {
+        let square = Expression::mul(Expression::var("x"), Expression::var("x"));
+        assert_eq!(
+            square.sample("x", 0, 3, 1),
+            vec![(0, Ok(0)), (1, Ok(1)), (2, Ok(4)), (3, Ok(9))]
+        );
+    }
+
+    #[test]
+    fn plot_of_a_linear_function_rises() {
+        let line = Expression::add(
+            Expression::mul(Expression::num(2), Expression::var("x")),
+            Expression::num(1),
+        );
+        let plot = line.ascii_plot("x", 0, 9);
+        let rows: Vec<&str> = plot.lines().collect();
+        // The row each column's point is drawn in, counted from the top.
+        let heights: Vec<usize> = (0..10)
+            .map(|col| {
+                rows.iter()
+                    .position(|row| row.chars().nth(col) == Some('*'))
+                    .expect("every column has a point")
+            })
+            .collect();
+        assert!(heights.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(heights[9] < heights[0]);
+    }
+
+    #[test]
+    fn ascii_tree_has_only_ascii_bytes() {
+        let expr = Expression::add(
+            Expression::num(1),
+            Expression::mul(Expression::num(2), Expression::neg(Expression::var("x"))),
+        );
+        let mut out = Vec::new();
+        expr.write_tree_ascii(&mut out).unwrap();
+        assert!(out.is_ascii());
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 6);
+    }
+
+    #[test]
+    fn potential_undefined_flags_unproven_divisors() {
+        let expr = Expression::add(
+            Expression::div(Expression::var("a"), Expression::var("b")),
+            Expression::div(Expression::num(5), Expression::num(2)),
+        );
+        assert_eq!(expr.potential_undefined(), vec!["a / b".to_string()]);
+    }
+
+    #[test]
+    fn tree_written_to_a_buffer() {
+        let expr = Expression::add(
+            Expression::num(1),
+            Expression::mul(Expression::num(2), Expression::num(3)),
+        );
+        let mut out = Vec::new();
+        expr.write_tree(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "+\n  ├ 1\n  └ *\n    ├ 2\n    └ 3\n"
+        );
+    }
+
+    #[test]
+    fn linearize_mixed_additive_chain() {
+        // 1 + 2 * x - 3 + 4
+        let expr = Expression::add(
+            Expression::sub(
+                Expression::add(
+                    Expression::num(1),
+                    Expression::mul(Expression::num(2), Expression::var("x")),
+                ),
+                Expression::num(3),
+            ),
+            Expression::num(4),
+        );
+        assert_eq!(
+            expr.linearize_chain(),
+            Some((
+                Expression::num(1),
+                vec![
+                    (
+                        BinOp::Add,
+                        Expression::mul(Expression::num(2), Expression::var("x"))
+                    ),
+                    (BinOp::Sub, Expression::num(3)),
+                    (BinOp::Add, Expression::num(4)),
+                ]
+            ))
+        );
+        assert_eq!(Expression::num(1).linearize_chain(), None);
+    }
+
+    #[test]
+    fn annotated_dot_carries_the_root_value() {
+        let expr = Expression::mul(
+            Expression::add(Expression::num(2), Expression::num(3)),
+            Expression::num(4),
+        );
+        let dot = expr.to_dot_annotated();
+        assert!(dot.contains("n0 [label=\"*\", tooltip=\"20\"];"));
+        assert!(dot.contains("n1 [label=\"+\", tooltip=\"5\"];"));
+        assert!(!expr.to_dot().contains("tooltip"));
+    }
+
+    #[test]
+    fn latex() {
+        let one_plus_two = Expression::add(Expression::num(1), Expression::num(2));
+        let fraction = Expression::div(
+            one_plus_two.clone(),
+            Expression::add(Expression::num(3), Expression::num(4)),
+        );
+        assert_eq!(fraction.to_latex(), "\\frac{1+2}{3+4}");
+        assert_eq!(
+            Expression::pow(
+                Expression::num(2),
+                Expression::add(Expression::var("x"), Expression::num(1))
+            )
+            .to_latex(),
+            "2^{x+1}"
+        );
+        assert_eq!(Expression::neg(one_plus_two).to_latex(), "-(1+2)");
+    }
+
+    fn parse(input: &str) -> Expression {
+        crate::parser::Parser::new(input)
+            .and_then(|mut parser| parser.parse())
+            .unwrap_or_else(|e| panic!("{}: {}", input, e))
+    }
+
+    fn reparse(expr: &Expression) -> Expression {
+        parse(&expr.to_string())
+    }
+
+    #[test]
+    fn negation_round_trips() {
+        let n = Expression::num;
+        let negated_product = Expression::neg(Expression::mul(n(2), n(3)));
+        let negated_sum = Expression::neg(Expression::add(n(2), n(3)));
+        let times_negated = Expression::mul(n(2), Expression::neg(n(3)));
+        assert_eq!(negated_product.to_string(), "-(2 * 3)");
+        assert_eq!(negated_sum.to_string(), "-(2 + 3)");
+        assert_eq!(times_negated.to_string(), "2 * -3");
+        for expr in [
+            negated_product,
+            negated_sum,
+            times_negated,
+            Expression::neg(Expression::pow(n(2), n(3))),
+            Expression::pow(Expression::neg(n(2)), n(3)),
+            Expression::pow(n(2), Expression::neg(n(3))),
+            Expression::factorial(Expression::neg(n(2))),
+        ] {
+            assert_eq!(reparse(&expr), expr);
+        }
+    }
+
+    #[test]
+    fn may_divide_by_zero() {
+        let one_over = |divisor| Expression::div(Expression::num(1), divisor);
+        assert!(one_over(Expression::num(0)).may_divide_by_zero());
+        // Not provably nonzero without evaluating it.
+        assert!(
+            one_over(Expression::sub(Expression::num(2), Expression::num(2))).may_divide_by_zero()
+        );
+        assert!(!one_over(Expression::num(2)).may_divide_by_zero());
+    }
+
+    #[test]
+    fn precedence_table() {
+        assert!(BinOp::Mul.precedence() > BinOp::Add.precedence());
+        assert!(BinOp::Pow.precedence() > BinOp::Div.precedence());
+        assert_eq!(BinOp::Sub.associativity(), Associativity::Left);
+        assert_eq!(BinOp::Pow.associativity(), Associativity::Right);
+    }
+
+    #[test]
+    fn printing_round_trips_through_the_parser() {
+        for input in [
+            "1 - (2 - 3)",
+            "1 - 2 - 3",
+            "(1 + 2) * 3",
+            "2 * (3 / 4)",
+            "8 / 4 / 2",
+            "2 ** 3 ** 2",
+            "(2 ** 3) ** 2",
+            "(1 < 2) == (3 > 4)",
+            "a ? b : c ? d : e",
+            "(a ? b : c) ? d : e",
+            "(let x = 1 in x) + 2",
+        ] {
+            let expr = parse(input);
+            assert_eq!(expr.to_string(), input);
+            assert_eq!(reparse(&expr), expr);
+        }
+    }
+
+    #[test]
+    fn map_numbers_doubles_every_literal() {
+        let doubled = parse("2 + 3 * 4").map_numbers(|n| n * 2);
+        assert_eq!(doubled, parse("4 + 6 * 8"));
+        assert_eq!(doubled.literals(), vec![4, 6, 8]);
+        assert_eq!(doubled.evaluate(), Ok(52));
+    }
+
+    #[test]
+    fn depth_limited_tree() {
+        // The literal `d` sits at depth `d + 1`, down to depth 10.
+        let tree = (0..10).rev().fold(Expression::num(10), |deeper, d| {
+            Expression::add(Expression::num(d), deeper)
+        });
+        let mut out = Vec::new();
+        let style = TreeStyle {
+            max_depth: Some(2),
+            ..TreeStyle::default()
+        };
+        tree.write_tree_styled(&mut out, style).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains('…'));
+        let labels: Vec<&str> = out
+            .lines()
+            .map(|line| line.trim_start_matches([' ', '│', '├', '└']))
+            .collect();
+        assert_eq!(labels, ["+", "0", "+", "1", "+", "…"]);
+    }
+
+    #[test]
+    fn negated_operands_print_as_the_opposite_operator() {
+        let n = Expression::num;
+        let cases = [
+            (Expression::add(n(3), Expression::neg(n(5))), "3 - 5"),
+            (Expression::sub(n(3), Expression::neg(n(5))), "3 + 5"),
+            (Expression::add(n(3), n(-5)), "3 - 5"),
+            (Expression::sub(n(3), n(-5)), "3 + 5"),
+            // `i64::MIN` has no positive counterpart to subtract instead.
+            (
+                Expression::sub(n(-1), n(i64::MIN)),
+                "-1 - -9223372036854775808",
+            ),
+        ];
+        for (expr, printed) in cases {
+            assert_eq!(expr.to_string(), printed);
+            assert_eq!(reparse(&expr).evaluate(), expr.evaluate());
+        }
+    }
+
+    #[test]
+    fn fold_sums_literals() {
+        fn manual_sum(expr: &Expression) -> i64 {
+            let own = match expr {
+                Expression::Number(n) => *n,
+                _ => 0,
+            };
+            own + expr.children().into_iter().map(manual_sum).sum::<i64>()
+        }
+
+        let expr = parse("(1 + 2) * -3 - max(4, 5!) + (x ? 6 : 7)");
+        let folded = expr.fold(&mut |node, children: Vec<i64>| match node {
+            Expression::Number(n) => *n,
+            _ => children.iter().sum(),
+        });
+        assert_eq!(folded, manual_sum(&expr));
+        assert_eq!(folded, 1 + 2 + 3 + 4 + 5 + 6 + 7);
+        assert_eq!(expr.node_count(), expr.iter().count());
+    }
+
+    #[test]
+    fn operators_with_repetitions() {
+        let mut operators = parse("(2+3)*(4-1)").operators();
+        operators.sort_unstable();
+        assert_eq!(operators, ["*", "+", "-"]);
+        assert_eq!(parse("-(1 + 2) + -x").operators(), ["+", "neg", "+", "neg"]);
+        assert!(parse("42").operators().is_empty());
+    }
+
+    #[test]
+    fn iter_is_pre_order() {
+        let expr = parse("(1+2)*3");
+        let nodes: Vec<&Expression> = expr.iter().collect();
+        assert_eq!(
+            nodes,
+            [
+                &expr,
+                &parse("1 + 2"),
+                &Expression::num(1),
+                &Expression::num(2),
+                &Expression::num(3),
+            ]
+        );
+        assert_eq!(nodes.len(), expr.node_count());
+        assert_eq!((&expr).into_iter().count(), expr.node_count());
+    }
+
+    #[test]
+    fn builders() {
+        let expr = Expression::add(1.into(), Expression::mul(2.into(), 3.into()));
+        assert_eq!(expr, parse("1 + 2 * 3"));
+        assert_eq!(expr.evaluate(), Ok(7));
+        assert_eq!(
+            Expression::neg(Expression::pow(Expression::var("x"), 2.into())),
+            parse("-x ** 2")
+        );
+        assert_eq!(
+            Expression::let_in("x", 4.into(), Expression::factorial(Expression::var("x")))
+                .evaluate(),
+            Ok(24)
+        );
+    }
+
+    #[test]
+    fn substitute() {
+        let expr = parse("x * 2 + y");
+        let partial = expr.substitute("x", 5);
+        assert_eq!(partial, parse("5 * 2 + y"));
+        assert_eq!(partial.simplify(), parse("10 + y"));
+        assert_eq!(partial.substitute("y", 1).evaluate(), Ok(11));
+        // A `let` that rebinds the name shadows it in its body.
+        assert_eq!(
+            parse("x + (let x = 1 in x)").substitute("x", 5),
+            parse("5 + (let x = 1 in x)")
+        );
+    }
+
+    #[test]
+    fn annotated_tree() {
+        let annotated = |input| {
+            let style = TreeStyle {
+                annotate: true,
+                ..TreeStyle::default()
+            };
+            let mut out = Vec::new();
+            parse(input).write_tree_styled(&mut out, style).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            annotated("9223372036854775807 * 2"),
+            "* [overflow]\n  ├ 9223372036854775807\n  └ 2\n"
+        );
+        assert_eq!(
+            annotated("(1 / 0) + 1"),
+            "+ [div0]\n  ├ / [div0]\n  │ ├ 1\n  │ └ 0\n  └ 1\n"
+        );
+        // Variables in the body take the value their `let` binds.
+        assert_eq!(
+            annotated("let x = 2 in x * 3"),
+            "let x [6]\n  ├ 2\n  └ * [6]\n    ├ x [2]\n    └ 3\n"
+        );
+    }
+
+    #[test]
+    fn walk_mut_doubles_numbers() {
+        let mut expr = parse("1 + 2 * -(3 - x)");
+        expr.walk_mut(&mut |node| {
+            if let Expression::Number(n) = node {
+                *n *= 2;
+            }
+        });
+        assert_eq!(expr, parse("2 + 4 * -(6 - x)"));
+
+        let mut visited = Vec::new();
+        parse("(1 + 2) * 3").walk_mut(&mut |node| visited.push(node.to_string()));
+        assert_eq!(visited, ["1", "2", "1 + 2", "3", "(1 + 2) * 3"]);
+    }
+
+    #[test]
+    fn custom_operator_symbols() {
+        let style = FormatStyle {
+            mul: "×",
+            div: "÷",
+            neg: "−",
+            ..FormatStyle::default()
+        };
+        assert_eq!(parse("2 * 3").to_string_styled(&style), "2 × 3");
+        assert_eq!(
+            parse("-(8 / 2) * 3").to_string_styled(&style),
+            "−(8 ÷ 2) × 3"
+        );
+        assert_eq!(
+            parse("2 * 3").to_string_styled(&FormatStyle::default()),
+            "2 * 3"
+        );
+    }
+
+    #[test]
+    fn full_parens() {
+        assert_eq!(parse("2 + 3 * 4").to_string_full_parens(), "(2 + (3 * 4))");
+        assert_eq!(parse("-(2 + 3)").to_string_full_parens(), "(-(2 + 3))");
+        assert_eq!(parse("7").to_string_full_parens(), "7");
+        let expr = parse("1 - (2 - 3) ** 2!");
+        assert_eq!(parse(&expr.to_string_full_parens()), expr);
+    }
+
+    #[test]
+    fn display_matches_write_expr() {
+        let n = Expression::num;
+        let cases = [
+            // Precedence.
+            (
+                Expression::mul(Expression::add(n(1), n(2)), n(3)),
+                "(1 + 2) * 3",
+            ),
+            (
+                Expression::add(n(1), Expression::mul(n(2), n(3))),
+                "1 + 2 * 3",
+            ),
+            // Associativity.
+            (
+                Expression::sub(n(1), Expression::sub(n(2), n(3))),
+                "1 - (2 - 3)",
+            ),
+            (
+                Expression::sub(Expression::sub(n(1), n(2)), n(3)),
+                "1 - 2 - 3",
+            ),
+            (
+                Expression::pow(Expression::pow(n(2), n(3)), n(2)),
+                "(2 ** 3) ** 2",
+            ),
+            (
+                Expression::pow(n(2), Expression::pow(n(3), n(2))),
+                "2 ** 3 ** 2",
+            ),
+            // Unary operators.
+            (Expression::neg(Expression::add(n(1), n(2))), "-(1 + 2)"),
+            (Expression::factorial(Expression::neg(n(3))), "(-3)!"),
+            (
+                Expression::mul(Expression::neg(n(2)), Expression::factorial(n(3))),
+                "-2 * 3!",
+            ),
+        ];
+        for (expr, expected) in cases {
+            let mut written = String::new();
+            expr.write_expr(&mut written).unwrap();
+            assert_eq!(format!("{}", expr), expected);
+            assert_eq!(written, expected);
+        }
+    }
+
+    fn tree(input: &str, style: TreeStyle) -> String {
+        let mut out = Vec::new();
+        parse(input).write_tree_styled(&mut out, style).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn colored_tree() {
+        let style = TreeStyle {
+            color: true,
+            ..TreeStyle::default()
+        };
+        assert_eq!(
+            tree("2 * x", style),
+            "\x1b[36m*\x1b[0m\n  ├ \x1b[33m2\x1b[0m\n  └ x\n"
+        );
+    }
+
+    #[test]
+    fn uncolored_tree_has_no_escapes() {
+        assert_eq!(tree("2 * x", TreeStyle::default()), "*\n  ├ 2\n  └ x\n");
+    }
+
+    #[test]
+    fn latex_variables() {
+        assert_eq!(parse("x ** 2 + rate").to_latex(), "x^{2}+\\mathit{rate}");
+        assert_eq!(
+            parse("let total = 2 in total * y").to_latex(),
+            "\\mathbf{let}\\ \\mathit{total} = 2\\ \\mathbf{in}\\ \\mathit{total} \\cdot y"
+        );
+    }
+}
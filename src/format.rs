@@ -0,0 +1,160 @@
+use crate::expression::Expression;
+
+pub const SUPPORTED_RADIXES: [u32; 4] = [2, 8, 10, 16];
+
+pub fn format_result(value: i64, radix: u32) -> Option<String> {
+    SUPPORTED_RADIXES.contains(&radix).then(|| {
+        render_value(
+            value.into(),
+            &FormatOptions {
+                radix,
+                ..FormatOptions::default()
+            },
+        )
+    })
+}
+
+/// Decimal digits in groups of three separated by commas: `1,000,000`.
+pub fn format_grouped(value: i64) -> String {
+    format_grouped_with(value, ',')
+}
+
+pub fn format_grouped_with(value: i64, separator: char) -> String {
+    render_value(
+        value.into(),
+        &FormatOptions {
+            group: Some(separator),
+            ..FormatOptions::default()
+        },
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// One of `SUPPORTED_RADIXES`; anything else is written in decimal.
+    pub radix: u32,
+    /// Digit separator. Decimal and octal digits are grouped by three,
+    /// binary and hexadecimal ones by four, as in `0x8000_0000`.
+    pub group: Option<char>,
+    /// Whether positive values get a leading `+`.
+    pub plus_sign: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            radix: 10,
+            group: None,
+            plus_sign: false,
+        }
+    }
+}
+
+/// Writes `value` as sign, radix prefix and digits, so `-0x10` rather than a
+/// two's complement pattern. Works on the unsigned magnitude, which `i128::MIN`
+/// has even though it cannot be negated.
+pub fn render_value(value: i128, opts: &FormatOptions) -> String {
+    let magnitude = value.unsigned_abs();
+    let (prefix, digits, group_size) = match opts.radix {
+        2 => ("0b", format!("{:b}", magnitude), 4),
+        8 => ("0o", format!("{:o}", magnitude), 3),
+        16 => ("0x", format!("{:X}", magnitude), 4),
+        _ => ("", magnitude.to_string(), 3),
+    };
+    let sign = if value < 0 {
+        "-"
+    } else if value > 0 && opts.plus_sign {
+        "+"
+    } else {
+        ""
+    };
+
+    let mut out = format!("{}{}", sign, prefix);
+    for (i, digit) in digits.chars().enumerate() {
+        if let Some(separator) = opts.group
+            && i > 0
+            && (digits.len() - i).is_multiple_of(group_size)
+        {
+            out.push(separator);
+        }
+        out.push(digit);
+    }
+    out
+}
+
+// `Display` already emits the minimal parenthesization, so anything else
+// (extra parens, different spacing) is rejected.
+pub fn minimal_parens_matches(expr: &Expression, rendered: &str) -> bool {
+    expr.to_string() == rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn result_in_each_radix() {
+        let cases = [
+            (2, "0b1010", "-0b1010"),
+            (8, "0o12", "-0o12"),
+            (10, "10", "-10"),
+            (16, "0xA", "-0xA"),
+        ];
+        for (radix, positive, negative) in cases {
+            assert_eq!(format_result(10, radix).as_deref(), Some(positive));
+            assert_eq!(format_result(-10, radix).as_deref(), Some(negative));
+        }
+        assert_eq!(format_result(10, 3), None);
+    }
+
+    #[test]
+    fn minimal_parens() {
+        // (1 + 2) * 3
+        let expr = Expression::mul(
+            Expression::add(Expression::num(1), Expression::num(2)),
+            Expression::num(3),
+        );
+        assert!(minimal_parens_matches(&expr, "(1 + 2) * 3"));
+        assert!(!minimal_parens_matches(&expr, "((1 + 2) * 3)"));
+        assert!(!minimal_parens_matches(&expr, "((1 + 2)) * 3"));
+        assert!(!minimal_parens_matches(&expr, "(1 + 2) * (3)"));
+        assert!(!minimal_parens_matches(&expr, "1 + 2 * 3"));
+    }
+
+    #[test]
+    fn grouped_thousands() {
+        assert_eq!(format_grouped(1000000), "1,000,000");
+        assert_eq!(format_grouped(-1234567), "-1,234,567");
+        assert_eq!(format_grouped(0), "0");
+        assert_eq!(format_grouped(999), "999");
+        assert_eq!(format_grouped_with(1000000000, '_'), "1_000_000_000");
+        assert_eq!(format_grouped_with(-1000, ' '), "-1 000");
+    }
+
+    #[test]
+    fn render_min_value() {
+        let options = |radix| FormatOptions {
+            radix,
+            group: Some(','),
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            render_value(i64::MIN.into(), &options(10)),
+            "-9,223,372,036,854,775,808"
+        );
+        assert_eq!(
+            render_value(i64::MIN.into(), &options(16)),
+            "-0x8000,0000,0000,0000"
+        );
+        let plus = FormatOptions {
+            plus_sign: true,
+            ..options(10)
+        };
+        assert_eq!(
+            render_value(i128::MIN, &options(10)),
+            "-170,141,183,460,469,231,731,687,303,715,884,105,728"
+        );
+        assert_eq!(render_value(1234, &plus), "+1,234");
+        assert_eq!(render_value(0, &plus), "0");
+    }
+}
@@ -0,0 +1,197 @@
+use crate::expression::Expression;
+
+pub trait Rng {
+    fn next_u64(&mut self) -> u64;
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+/// Small xorshift64 generator, good enough for test data and reproducible
+/// from its seed.
+#[derive(Debug, Clone)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // The all-zero state is a fixed point of xorshift.
+        XorShift {
+            state: if seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                seed
+            },
+        }
+    }
+}
+
+impl Rng for XorShift {
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperatorWeights {
+    pub number: u32,
+    pub variable: u32,
+    pub addition: u32,
+    pub subtraction: u32,
+    pub multiplication: u32,
+    pub division: u32,
+    pub remainder: u32,
+    pub negation: u32,
+    pub factorial: u32,
+    pub power: u32,
+}
+
+impl Default for OperatorWeights {
+    fn default() -> Self {
+        OperatorWeights {
+            number: 4,
+            variable: 1,
+            addition: 2,
+            subtraction: 2,
+            multiplication: 2,
+            division: 1,
+            remainder: 1,
+            negation: 1,
+            factorial: 1,
+            power: 1,
+        }
+    }
+}
+
+const VARIABLES: [&str; 3] = ["x", "y", "z"];
+const MAX_LITERAL: u64 = 100;
+
+pub fn random_expression(rng: &mut impl Rng, max_depth: usize) -> Expression {
+    random_expression_with(rng, max_depth, &OperatorWeights::default())
+}
+
+pub fn random_expression_with(
+    rng: &mut impl Rng,
+    max_depth: usize,
+    weights: &OperatorWeights,
+) -> Expression {
+    // Literals are never negative so that printing and re-parsing yields an
+    // equivalent tree; negative values come from `Negation` nodes instead.
+    let all = [
+        weights.number,
+        weights.variable,
+        weights.addition,
+        weights.subtraction,
+        weights.multiplication,
+        weights.division,
+        weights.remainder,
+        weights.negation,
+        weights.factorial,
+        weights.power,
+    ];
+    let candidates = if max_depth == 0 { &all[..2] } else { &all[..] };
+
+    let depth = max_depth.saturating_sub(1);
+    match pick(rng, candidates) {
+        Some(0) | None => Expression::Number(rng.below(MAX_LITERAL) as i64),
+        Some(1) => {
+            let name = VARIABLES[rng.below(VARIABLES.len() as u64) as usize];
+            Expression::Variable(name.to_string())
+        }
+        Some(2) => Expression::Addition(child(rng, depth, weights), child(rng, depth, weights)),
+        Some(3) => Expression::Subtraction(child(rng, depth, weights), child(rng, depth, weights)),
+        Some(4) => {
+            Expression::Multiplication(child(rng, depth, weights), child(rng, depth, weights))
+        }
+        Some(5) => Expression::Division(child(rng, depth, weights), child(rng, depth, weights)),
+        Some(6) => Expression::Remainder(child(rng, depth, weights), child(rng, depth, weights)),
+        Some(7) => Expression::Negation(child(rng, depth, weights)),
+        Some(8) => Expression::Factorial(child(rng, depth, weights)),
+        Some(_) => Expression::Power(child(rng, depth, weights), child(rng, depth, weights)),
+    }
+}
+
+fn child(rng: &mut impl Rng, depth: usize, weights: &OperatorWeights) -> Box<Expression> {
+    Box::new(random_expression_with(rng, depth, weights))
+}
+
+fn pick(rng: &mut impl Rng, weights: &[u32]) -> Option<usize> {
+    let total: u64 = weights.iter().map(|&w| w as u64).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut roll = rng.below(total);
+    weights.iter().position(|&w| {
+        if roll < w as u64 {
+            true
+        } else {
+            roll -= w as u64;
+            false
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Environment;
+    use crate::parser::Parser;
+
+    fn depth(expr: &Expression) -> usize {
+        expr.fold(&mut |_, children: Vec<usize>| 1 + children.into_iter().max().unwrap_or(0))
+    }
+
+    // Printing may rewrite `a + -b` as `a - b`, so a reparsed tree need only
+    // evaluate to the same value.
+    #[test]
+    fn generated_expressions_round_trip() {
+        let env: Environment = VARIABLES
+            .iter()
+            .zip([3, -2, 5])
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        let mut rng = XorShift::new(42);
+        for _ in 0..1000 {
+            let expr = random_expression(&mut rng, 6);
+            assert!(depth(&expr) <= 7);
+            let printed = expr.to_string();
+            let reparsed = Parser::new(&printed)
+                .and_then(|mut parser| parser.parse())
+                .unwrap_or_else(|e| panic!("{}: {}", printed, e));
+            assert_eq!(
+                reparsed.evaluate_with(&env).ok(),
+                expr.evaluate_with(&env).ok(),
+                "{}",
+                printed
+            );
+        }
+    }
+
+    #[test]
+    fn zero_weights_leave_only_literals() {
+        let weights = OperatorWeights {
+            number: 1,
+            variable: 0,
+            addition: 0,
+            subtraction: 0,
+            multiplication: 0,
+            division: 0,
+            remainder: 0,
+            negation: 0,
+            factorial: 0,
+            power: 0,
+        };
+        let mut rng = XorShift::new(7);
+        for _ in 0..100 {
+            let expr = random_expression_with(&mut rng, 5, &weights);
+            assert!(matches!(expr, Expression::Number(0..100)));
+        }
+    }
+}
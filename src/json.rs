@@ -0,0 +1,235 @@
+use std::fmt::Write;
+
+use crate::expression::Expression;
+
+impl Expression {
+    /// Externally tagged, the way serde would derive it: `{"Number":1}`,
+    /// `{"Addition":[{"Number":1},{"Number":2}]}`, `{"Negation":{...}}`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    fn write_json(&self, out: &mut String) {
+        match self {
+            Expression::Number(n) => write!(out, "{{\"Number\":{}}}", n).unwrap(),
+            Expression::Variable(name) => {
+                out.push_str("{\"Variable\":");
+                write_json_string(out, name);
+                out.push('}');
+            }
+            Expression::Addition(l, r) => write_json_pair(out, "Addition", l, r),
+            Expression::Subtraction(l, r) => write_json_pair(out, "Subtraction", l, r),
+            Expression::Multiplication(l, r) => write_json_pair(out, "Multiplication", l, r),
+            Expression::Division(l, r) => write_json_pair(out, "Division", l, r),
+            Expression::Remainder(l, r) => write_json_pair(out, "Remainder", l, r),
+            Expression::Power(l, r) => write_json_pair(out, "Power", l, r),
+            Expression::Negation(expr) => {
+                out.push_str("{\"Negation\":");
+                expr.write_json(out);
+                out.push('}');
+            }
+            Expression::Factorial(expr) => {
+                out.push_str("{\"Factorial\":");
+                expr.write_json(out);
+                out.push('}');
+            }
+            Expression::Comparison { op, left, right } => {
+                write!(out, "{{\"Comparison\":{{\"op\":\"{:?}\",\"left\":", op).unwrap();
+                left.write_json(out);
+                out.push_str(",\"right\":");
+                right.write_json(out);
+                out.push_str("}}");
+            }
+            Expression::Conditional { cond, then, els } => {
+                out.push_str("{\"Conditional\":{\"cond\":");
+                cond.write_json(out);
+                out.push_str(",\"then\":");
+                then.write_json(out);
+                out.push_str(",\"els\":");
+                els.write_json(out);
+                out.push_str("}}");
+            }
+            Expression::Let { name, value, body } => {
+                out.push_str("{\"Let\":{\"name\":");
+                write_json_string(out, name);
+                out.push_str(",\"value\":");
+                value.write_json(out);
+                out.push_str(",\"body\":");
+                body.write_json(out);
+                out.push_str("}}");
+            }
+            Expression::Call { name, args } => {
+                out.push_str("{\"Call\":{\"name\":");
+                write_json_string(out, name);
+                out.push_str(",\"args\":[");
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    arg.write_json(out);
+                }
+                out.push_str("]}}");
+            }
+        }
+    }
+}
+
+// A field value in `to_json_tree`: an already encoded scalar, a subtree or a
+// list of subtrees.
+enum TreeField<'a> {
+    Scalar(String),
+    Node(&'a Expression),
+    Nodes(&'a [Expression]),
+}
+
+fn scalar(s: &str) -> TreeField<'_> {
+    let mut out = String::new();
+    write_json_string(&mut out, s);
+    TreeField::Scalar(out)
+}
+
+impl Expression {
+    /// Indented JSON keyed by operator rather than by variant name:
+    /// `{"num": 5}`, `{"var": "x"}`, `{"op": "+", "left": ..., "right": ...}`,
+    /// `{"op": "neg" | "!", "operand": ...}`, `{"call": "abs", "args": [...]}`,
+    /// `{"op": "?:", "cond": ..., "then": ..., "else": ...}` and
+    /// `{"op": "let", "name": "x", "value": ..., "body": ...}`. Binary
+    /// operators and comparisons use their infix symbol, so `**` for a power.
+    pub fn to_json_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_json_tree(&mut out, 0);
+        out
+    }
+
+    fn write_json_tree(&self, out: &mut String, indent: usize) {
+        let fields = match self {
+            Expression::Number(n) => vec![("num", TreeField::Scalar(n.to_string()))],
+            Expression::Variable(name) => vec![("var", scalar(name))],
+            Expression::Negation(expr) => {
+                vec![("op", scalar("neg")), ("operand", TreeField::Node(expr))]
+            }
+            Expression::Factorial(expr) => {
+                vec![("op", scalar("!")), ("operand", TreeField::Node(expr))]
+            }
+            Expression::Call { name, args } => {
+                vec![("call", scalar(name)), ("args", TreeField::Nodes(args))]
+            }
+            Expression::Comparison { op, left, right } => vec![
+                ("op", scalar(op.symbol())),
+                ("left", TreeField::Node(left)),
+                ("right", TreeField::Node(right)),
+            ],
+            Expression::Conditional { cond, then, els } => vec![
+                ("op", scalar("?:")),
+                ("cond", TreeField::Node(cond)),
+                ("then", TreeField::Node(then)),
+                ("else", TreeField::Node(els)),
+            ],
+            Expression::Let { name, value, body } => vec![
+                ("op", scalar("let")),
+                ("name", scalar(name)),
+                ("value", TreeField::Node(value)),
+                ("body", TreeField::Node(body)),
+            ],
+            _ => {
+                let (op, left, right) = self.as_binary().expect("every other variant is binary");
+                vec![
+                    ("op", scalar(op.symbol())),
+                    ("left", TreeField::Node(left)),
+                    ("right", TreeField::Node(right)),
+                ]
+            }
+        };
+
+        out.push_str("{\n");
+        for (i, (key, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                out.push_str(",\n");
+            }
+            write!(out, "{:width$}\"{}\": ", "", key, width = 2 * (indent + 1)).unwrap();
+            match value {
+                TreeField::Scalar(text) => out.push_str(text),
+                TreeField::Node(expr) => expr.write_json_tree(out, indent + 1),
+                TreeField::Nodes([]) => out.push_str("[]"),
+                TreeField::Nodes(exprs) => {
+                    out.push_str("[\n");
+                    for (j, expr) in exprs.iter().enumerate() {
+                        if j > 0 {
+                            out.push_str(",\n");
+                        }
+                        write!(out, "{:width$}", "", width = 2 * (indent + 2)).unwrap();
+                        expr.write_json_tree(out, indent + 2);
+                    }
+                    write!(out, "\n{:width$}]", "", width = 2 * (indent + 1)).unwrap();
+                }
+            }
+        }
+        write!(out, "\n{:width$}}}", "", width = 2 * indent).unwrap();
+    }
+}
+
+fn write_json_pair(out: &mut String, tag: &str, left: &Expression, right: &Expression) {
+    write!(out, "{{\"{}\":[", tag).unwrap();
+    left.write_json(out);
+    out.push(',');
+    right.write_json(out);
+    out.push_str("]}");
+}
+
+pub(crate) fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input)
+            .and_then(|mut parser| parser.parse())
+            .expect("test input parses")
+    }
+
+    #[test]
+    fn json_tree_structure() {
+        assert_eq!(
+            parse("1 + 2").to_json_tree(),
+            "{\n  \"op\": \"+\",\n  \"left\": {\n    \"num\": 1\n  },\n  \"right\": {\n    \"num\": 2\n  }\n}"
+        );
+        assert_eq!(
+            parse("-x").to_json_tree(),
+            "{\n  \"op\": \"neg\",\n  \"operand\": {\n    \"var\": \"x\"\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn externally_tagged_json() {
+        assert_eq!(
+            parse("-(1 + x) * 2!").to_json(),
+            r#"{"Multiplication":[{"Negation":{"Addition":[{"Number":1},{"Variable":"x"}]}},{"Factorial":{"Number":2}}]}"#
+        );
+        assert_eq!(
+            parse("max(1, 2) < 3 ? 4 : 5").to_json(),
+            r#"{"Conditional":{"cond":{"Comparison":{"op":"Less","left":{"Call":{"name":"max","args":[{"Number":1},{"Number":2}]}},"right":{"Number":3}}},"then":{"Number":4},"els":{"Number":5}}}"#
+        );
+        assert_eq!(
+            parse("let x = 1 in x").to_json(),
+            r#"{"Let":{"name":"x","value":{"Number":1},"body":{"Variable":"x"}}}"#
+        );
+    }
+}
@@ -0,0 +1,35 @@
+#[cfg(feature = "bignum")]
+mod bigint;
+mod diff;
+mod eval;
+mod explain;
+mod expression;
+mod format;
+#[cfg(feature = "testutil")]
+pub mod r#gen;
+mod json;
+mod parser;
+mod range;
+mod rational;
+pub mod repl;
+mod rpn;
+mod simplify;
+mod statement;
+
+#[cfg(feature = "bignum")]
+pub use bigint::{BigInt, EvalValue, Precision};
+pub use diff::{DiffPath, Step};
+pub use eval::{Environment, EvalError, EvalFailure, EvalReport, IntegerWidth, OverflowMode};
+pub use expression::{
+    Associativity, BinOp, CompareOp, Expression, FormatStyle, Subexpressions, TreeStyle,
+};
+pub use format::{
+    FormatOptions, SUPPORTED_RADIXES, format_grouped, format_grouped_with, format_result,
+    minimal_parens_matches, render_value,
+};
+pub use parser::{
+    Lexer, LexerConfig, ParseError, Parser, Token, lex, lex_with_positions, lex_with_spans,
+    redundant_parentheses,
+};
+pub use rpn::{OpKind, RpnItem, eval_rpn};
+pub use statement::{Statement, propagate_constants, run_program, run_program_width};
@@ -1,401 +1,268 @@
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::process;
+use std::time::Instant;
 
-#[derive(Debug, Clone)]
-enum Expression {
-    Number(i64),
-    Addition(Box<Expression>, Box<Expression>),
-    Subtraction(Box<Expression>, Box<Expression>),
-    Multiplication(Box<Expression>, Box<Expression>),
-    Division(Box<Expression>, Box<Expression>),
-    Remainder(Box<Expression>, Box<Expression>),
-    Negation(Box<Expression>),
-}
+use arvore_sintatica::repl::{self, ReplConfig, ReplState};
+use arvore_sintatica::{
+    Environment, FormatOptions, LexerConfig, OverflowMode, TreeStyle, run_program,
+};
 
-impl Expression {
-    fn evaluate(&self) -> Option<i64> {
-        match self {
-            Expression::Number(n) => Some(*n),
-            Expression::Addition(left, right) => {
-                let v_left = left.evaluate()?;
-                let v_right = right.evaluate()?;
-                v_left.checked_add(v_right)
-            }
-            Expression::Subtraction(left, right) => {
-                let v_left = left.evaluate()?;
-                let v_right = right.evaluate()?;
-                v_left.checked_sub(v_right)
-            }
-            Expression::Multiplication(left, right) => {
-                let v_left = left.evaluate()?;
-                let v_right = right.evaluate()?;
-                v_left.checked_mul(v_right)
-            }
-            Expression::Division(left, right) => {
-                let v_left = left.evaluate()?;
-                let v_right = right.evaluate()?;
-                if v_right == 0 {
-                    return None;
-                }
-                v_left.checked_div(v_right)
-            }
-            Expression::Remainder(left, right) => {
-                let v_left = left.evaluate()?;
-                let v_right = right.evaluate()?;
-                if v_right == 0 {
-                    return None;
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Color is opt-in and never written to a pipe or file.
+    let tree_style = TreeStyle {
+        ascii: args.iter().any(|arg| arg == "--ascii"),
+        color: args.iter().any(|arg| arg == "--color")
+            && !args.iter().any(|arg| arg == "--no-color")
+            && io::stdout().is_terminal(),
+        ..TreeStyle::default()
+    };
+    // `%` is the remainder operator unless percent mode is asked for.
+    let lexer = LexerConfig {
+        percent: args.iter().any(|arg| arg == "--percent"),
+        ..LexerConfig::default()
+    };
+    // `--group` separates thousands with commas, `--group=_` with any other
+    // single character.
+    let group = args.iter().find_map(|arg| match arg.as_str() {
+        "--group" => Some(','),
+        _ => {
+            let separator = arg.strip_prefix("--group=")?;
+            let mut chars = separator.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => {
+                    eprintln!("error: --group expects a single separator character");
+                    process::exit(2);
                 }
-                v_left.checked_rem(v_right)
-            }
-            Expression::Negation(expr) => {
-                let v = expr.evaluate()?;
-                v.checked_neg()
             }
         }
-    }
+    });
+    let json = args.iter().any(|arg| arg == "--json");
+    // Piped input without `--batch` still goes through the REPL, so its `:`
+    // commands, `--quiet` and `--prompt` keep working.
+    let batch = args.iter().any(|arg| arg == "--batch");
 
-    fn print(&self) {
-        print!("{}", self.to_string_prec(0));
+    if let Some(i) = args.iter().position(|arg| arg == "--benchmark") {
+        // An optional count may follow the flag.
+        let count = match args.get(i + 1).filter(|arg| !arg.starts_with("--")) {
+            Some(count) => count.parse().unwrap_or_else(|_| {
+                eprintln!("error: --benchmark expects a number of expressions");
+                process::exit(2);
+            }),
+            None => 10_000,
+        };
+        benchmark(count);
+        return;
     }
 
-    fn to_string_prec(&self, parent_prec: u8) -> String {
-        match self {
-            Expression::Number(n) => n.to_string(),
-            Expression::Negation(expr) => {
-                let s = format!("-{}", expr.to_string_prec(5));
-                if parent_prec > 5 {
-                    format!("({})", s)
-                } else {
-                    s
-                }
-            }
-            Expression::Multiplication(left, right) => {
-                let s = format!("{} * {}", left.to_string_prec(3), right.to_string_prec(4));
-                if parent_prec > 3 {
-                    format!("({})", s)
-                } else {
-                    s
-                }
-            }
-            Expression::Division(left, right) => {
-                let s = format!("{} / {}", left.to_string_prec(3), right.to_string_prec(4));
-                if parent_prec > 3 {
-                    format!("({})", s)
-                } else {
-                    s
-                }
+    if let Some(i) = args.iter().position(|arg| arg == "--repeat") {
+        let (Some(count), Some(source)) = (args.get(i + 1), args.get(i + 2)) else {
+            eprintln!("error: --repeat expects a count and an expression");
+            process::exit(2);
+        };
+        let count = match count.parse::<u32>() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                eprintln!("error: --repeat expects a positive count");
+                process::exit(2);
             }
-            Expression::Remainder(left, right) => {
-                let s = format!("{} % {}", left.to_string_prec(3), right.to_string_prec(4));
-                if parent_prec > 3 {
-                    format!("({})", s)
-                } else {
-                    s
-                }
-            }
-            Expression::Addition(left, right) => {
-                let s = format!("{} + {}", left.to_string_prec(1), right.to_string_prec(2));
-                if parent_prec > 1 {
-                    format!("({})", s)
-                } else {
-                    s
-                }
-            }
-            Expression::Subtraction(left, right) => {
-                let s = format!("{} - {}", left.to_string_prec(1), right.to_string_prec(2));
-                if parent_prec > 1 {
-                    format!("({})", s)
-                } else {
-                    s
-                }
-            }
-        }
+        };
+        repeat(&mut io::stdout().lock(), source, count, &lexer).unwrap_or_else(|e| io_failure(&e));
+        return;
     }
 
-    fn print_tree(&self) {
-        self.print_tree_recursive("", true);
+    if let Some(i) = args.iter().position(|arg| arg == "--ast-only") {
+        let Some(source) = args.get(i + 1) else {
+            eprintln!("error: --ast-only expects an expression");
+            process::exit(2);
+        };
+        match repl::ast_only(source, &lexer) {
+            Ok(tree) => println!("{}", tree),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
     }
 
-    fn print_tree_recursive(&self, prefix: &str, is_last: bool) {
-        let current_symbol = if is_last { "└" } else { "├" };
-        let child_prefix = if is_last { "  " } else { "│ " };
-
-        match self {
-            Expression::Number(n) => {
-                if !prefix.is_empty() {
-                    println!("{}{} {}", prefix, current_symbol, n);
-                } else {
-                    println!("{}", n);
-                }
-            }
-            Expression::Negation(expr) => {
-                if !prefix.is_empty() {
-                    println!("{}{} -", prefix, current_symbol);
-                } else {
-                    println!("-");
-                }
-                expr.print_tree_recursive(&format!("{}{}", prefix, child_prefix), true);
-            }
-            Expression::Addition(left, right) => {
-                if !prefix.is_empty() {
-                    println!("{}{} +", prefix, current_symbol);
-                } else {
-                    println!("+");
+    if let Some(i) = args.iter().position(|arg| arg == "--explain") {
+        let Some(source) = args.get(i + 1) else {
+            eprintln!("error: --explain expects an expression");
+            process::exit(2);
+        };
+        match repl::parse_input(source, &lexer) {
+            Ok(expr) => {
+                for line in expr.explain_precedence() {
+                    println!("{}", line);
                 }
-                left.print_tree_recursive(&format!("{}{}", prefix, child_prefix), false);
-                right.print_tree_recursive(&format!("{}{}", prefix, child_prefix), true);
             }
-            Expression::Subtraction(left, right) => {
-                if !prefix.is_empty() {
-                    println!("{}{} -", prefix, current_symbol);
-                } else {
-                    println!("-");
-                }
-                left.print_tree_recursive(&format!("{}{}", prefix, child_prefix), false);
-                right.print_tree_recursive(&format!("{}{}", prefix, child_prefix), true);
-            }
-            Expression::Multiplication(left, right) => {
-                if !prefix.is_empty() {
-                    println!("{}{} *", prefix, current_symbol);
-                } else {
-                    println!("*");
-                }
-                left.print_tree_recursive(&format!("{}{}", prefix, child_prefix), false);
-                right.print_tree_recursive(&format!("{}{}", prefix, child_prefix), true);
-            }
-            Expression::Division(left, right) => {
-                if !prefix.is_empty() {
-                    println!("{}{} /", prefix, current_symbol);
-                } else {
-                    println!("/");
-                }
-                left.print_tree_recursive(&format!("{}{}", prefix, child_prefix), false);
-                right.print_tree_recursive(&format!("{}{}", prefix, child_prefix), true);
-            }
-            Expression::Remainder(left, right) => {
-                if !prefix.is_empty() {
-                    println!("{}{} %", prefix, current_symbol);
-                } else {
-                    println!("%");
-                }
-                left.print_tree_recursive(&format!("{}{}", prefix, child_prefix), false);
-                right.print_tree_recursive(&format!("{}{}", prefix, child_prefix), true);
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(1);
             }
         }
+        return;
     }
-}
-
-struct Parser {
-    tokens: Vec<Token>,
-    pos: usize,
-}
-
-#[derive(Debug, Clone, PartialEq)]
-enum Token {
-    Number(i64),
-    Plus,
-    Minus,
-    Times,
-    Divide,
-    Modulo,
-    LeftParen,
-    RightParen,
-}
-
-impl Parser {
-    fn new(input: &str) -> Result<Self, String> {
-        let tokens = Self::tokenize(input)?;
-        Ok(Parser { tokens, pos: 0 })
-    }
-
-    fn tokenize(input: &str) -> Result<Vec<Token>, String> {
-        let mut tokens = Vec::new();
-        let mut chars = input.chars().peekable();
 
-        while let Some(&c) = chars.peek() {
-            match c {
-                ' ' | '\t' | '\n' => {
-                    chars.next();
-                }
-                '+' => {
-                    tokens.push(Token::Plus);
-                    chars.next();
-                }
-                '-' => {
-                    tokens.push(Token::Minus);
-                    chars.next();
-                }
-                '*' => {
-                    tokens.push(Token::Times);
-                    chars.next();
-                }
-                '/' => {
-                    tokens.push(Token::Divide);
-                    chars.next();
-                }
-                '%' => {
-                    tokens.push(Token::Modulo);
-                    chars.next();
-                }
-                '(' => {
-                    tokens.push(Token::LeftParen);
-                    chars.next();
-                }
-                ')' => {
-                    tokens.push(Token::RightParen);
-                    chars.next();
-                }
-                '0'..='9' => {
-                    let mut num_str = String::new();
-                    while let Some(&c) = chars.peek() {
-                        if c.is_ascii_digit() {
-                            num_str.push(c);
-                            chars.next();
-                        } else {
-                            break;
-                        }
-                    }
-                    let num = num_str.parse::<i64>()
-                        .map_err(|_| "Invalid number")?;
-                    tokens.push(Token::Number(num));
-                }
-                _ => return Err(format!("Invalid character: '{}'", c)),
+    if let Some(i) = args.iter().position(|arg| arg == "--file") {
+        let Some(path) = args.get(i + 1) else {
+            eprintln!("error: --file expects a path");
+            process::exit(2);
+        };
+        match repl::evaluate_file(Path::new(path), &lexer) {
+            Ok(Some(value)) => println!("{}", value),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(1);
             }
         }
-
-        Ok(tokens)
+        return;
     }
 
-    fn current(&self) -> Option<&Token> {
-        self.tokens.get(self.pos)
+    if batch {
+        repl::run_batch(
+            io::stdin().lock(),
+            &lexer,
+            &mut io::stdout().lock(),
+            &mut io::stderr().lock(),
+        )
+        .unwrap_or_else(|e| io_failure(&e));
+        return;
     }
-
-    fn advance(&mut self) {
-        self.pos += 1;
+    let mut config = ReplConfig {
+        quiet: args.iter().any(|arg| arg == "--quiet"),
+        json,
+        ..ReplConfig::default()
+    };
+    if let Some(i) = args.iter().position(|arg| arg == "--prompt") {
+        let Some(prompt) = args.get(i + 1) else {
+            eprintln!("error: --prompt expects a string");
+            process::exit(2);
+        };
+        config.prompt = prompt.clone();
     }
+    let mut state = ReplState {
+        tree_style,
+        lexer,
+        format: FormatOptions {
+            group,
+            plus_sign: args.iter().any(|arg| arg == "--plus"),
+            ..FormatOptions::default()
+        },
+        trace: args.iter().any(|arg| arg == "--trace"),
+        lint_parens: args.iter().any(|arg| arg == "--lint-parens"),
+        ..ReplState::default()
+    };
 
-    fn parse(&mut self) -> Result<Expression, String> {
-        self.parse_expression()
-    }
+    repl::run_interactive(
+        io::stdin().lock(),
+        &mut io::stdout().lock(),
+        &mut state,
+        &config,
+    )
+    .unwrap_or_else(|e| io_failure(&e));
+}
 
-    fn parse_expression(&mut self) -> Result<Expression, String> {
-        let mut left = self.parse_term()?;
+/// Parses `source` once and runs it `count` times, each time in a fresh
+/// environment, writing the result and how long the runs took to `out`.
+/// Every run must give the same result.
+fn repeat<W: Write>(out: &mut W, source: &str, count: u32, lexer: &LexerConfig) -> io::Result<()> {
+    let program = repl::parse_program_input(source, lexer).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    });
 
-        while let Some(token) = self.current() {
-            match token {
-                Token::Plus => {
-                    self.advance();
-                    let right = self.parse_term()?;
-                    left = Expression::Addition(Box::new(left), Box::new(right));
-                }
-                Token::Minus => {
-                    self.advance();
-                    let right = self.parse_term()?;
-                    left = Expression::Subtraction(Box::new(left), Box::new(right));
-                }
-                _ => break,
-            }
+    let run = || run_program(&program, &mut Environment::new(), OverflowMode::Checked);
+    let start = Instant::now();
+    let first = run();
+    for _ in 1..count {
+        if std::hint::black_box(run()) != first {
+            eprintln!("error: runs of '{}' gave different results", source);
+            process::exit(1);
         }
-
-        Ok(left)
     }
+    let elapsed = start.elapsed();
 
-    fn parse_term(&mut self) -> Result<Expression, String> {
-        let mut left = self.parse_factor()?;
-
-        while let Some(token) = self.current() {
-            match token {
-                Token::Times => {
-                    self.advance();
-                    let right = self.parse_factor()?;
-                    left = Expression::Multiplication(Box::new(left), Box::new(right));
-                }
-                Token::Divide => {
-                    self.advance();
-                    let right = self.parse_factor()?;
-                    left = Expression::Division(Box::new(left), Box::new(right));
-                }
-                Token::Modulo => {
-                    self.advance();
-                    let right = self.parse_factor()?;
-                    left = Expression::Remainder(Box::new(left), Box::new(right));
-                }
-                _ => break,
-            }
-        }
-
-        Ok(left)
-    }
-
-    fn parse_factor(&mut self) -> Result<Expression, String> {
-        match self.current() {
-            Some(Token::Number(n)) => {
-                let num = *n;
-                self.advance();
-                Ok(Expression::Number(num))
-            }
-            Some(Token::Minus) => {
-                self.advance();
-                let expr = self.parse_factor()?;
-                Ok(Expression::Negation(Box::new(expr)))
-            }
-            Some(Token::LeftParen) => {
-                self.advance();
-                let expr = self.parse_expression()?;
-                match self.current() {
-                    Some(Token::RightParen) => {
-                        self.advance();
-                        Ok(expr)
-                    }
-                    _ => Err("Expected ')'".to_string()),
-                }
-            }
-            _ => Err("Invalid expression".to_string()),
+    match first {
+        Ok(Some(value)) => writeln!(out, "{}", value)?,
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
         }
     }
+    writeln!(
+        out,
+        "{} runs in {:.3?} ({:.3?} per run)",
+        count,
+        elapsed,
+        elapsed / count
+    )
 }
 
-fn main() {
-    println!("=== Calculadora de Expressões ===");
-    println!("Digite uma expressão matemática (ou 'sair' para encerrar)");
-    println!("Exemplos: 10 + 20, (10 + 20) * 30\n");
+/// Times parsing and evaluating `count` random expressions, which are
+/// generated and printed before the clock starts.
+#[cfg(feature = "testutil")]
+fn benchmark(count: usize) {
+    use arvore_sintatica::r#gen::{OperatorWeights, XorShift, random_expression_with};
 
-    loop {
-        print!("Expressão: ");
-        io::stdout().flush().unwrap();
+    let weights = OperatorWeights {
+        variable: 0,
+        ..OperatorWeights::default()
+    };
+    let mut rng = XorShift::new(0x5EED);
+    let sources: Vec<String> = (0..count)
+        .map(|_| random_expression_with(&mut rng, 6, &weights).to_string())
+        .collect();
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        let input = input.trim();
-
-        if input.is_empty() {
-            continue;
+    let lexer = LexerConfig::default();
+    let start = Instant::now();
+    for source in &sources {
+        // Overflow and division by zero are part of the workload, not failures.
+        if let Ok(expr) = repl::parse_input(source, &lexer) {
+            let _ = std::hint::black_box(expr.evaluate());
         }
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{} expressions in {:.3?} ({:.0} expressions/sec)",
+        count,
+        elapsed,
+        count as f64 / elapsed.as_secs_f64()
+    );
+}
 
-        if input == "sair" || input == "exit" {
-            break;
-        }
+#[cfg(not(feature = "testutil"))]
+fn benchmark(_count: usize) {
+    eprintln!("error: --benchmark needs a build with the testutil feature");
+    process::exit(2);
+}
 
-        match Parser::new(input) {
-            Ok(mut parser) => {
-                match parser.parse() {
-                    Ok(expr) => {
-                        println!("\nExpressão simplificada:");
-                        expr.print();
-                        println!("\n");
+fn io_failure(error: &io::Error) -> ! {
+    eprintln!("error: {}", error);
+    process::exit(1);
+}
 
-                        println!("Árvore sintática:");
-                        expr.print_tree();
-                        println!();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                        match expr.evaluate() {
-                            Some(result) => println!("Resultado: {}\n", result),
-                            None => println!("Resultado: none\n"),
-                        }
-                    }
-                    Err(e) => println!("Erro ao fazer parse: {}\n", e),
-                }
-            }
-            Err(e) => println!("Erro: {}\n", e),
-        }
+    // `benchmark` only does the work with the testutil feature.
+    #[test]
+    #[cfg(feature = "testutil")]
+    fn benchmark_runs() {
+        benchmark(50);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn repeat_runs() {
+        let mut out = Vec::new();
+        repeat(&mut out, "2+2", 3, &LexerConfig::default()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("4\n3 runs in "));
+    }
+}
@@ -0,0 +1,1878 @@
+use std::fmt;
+use std::iter::{self, Peekable};
+use std::ops::Range;
+use std::str::CharIndices;
+
+use crate::expression::{Associativity, BinOp, CompareOp, Expression};
+use crate::statement::Statement;
+
+pub struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+    /// How many `|` bars are open around the current token.
+    open_bars: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// Literals are unsigned so that `9223372036854775808` can still be
+    /// negated into `i64::MIN`; the parser range-checks everything else.
+    Number(u64),
+    /// A literal with a nonzero fraction, worth `mantissa / 10^scale`; the
+    /// parser turns it into an exact division such as `1 / 2` for `.5`.
+    Decimal {
+        mantissa: u64,
+        scale: u32,
+    },
+    Identifier(String),
+    Plus,
+    Minus,
+    Times,
+    Divide,
+    Modulo,
+    Percent,
+    Power,
+    Bang,
+    LeftParen,
+    RightParen,
+    /// `[` and `]`, which group like parentheses.
+    LeftBracket,
+    RightBracket,
+    /// `{` and `}`, which group like parentheses.
+    LeftBrace,
+    RightBrace,
+    /// `|`, which both opens and closes an absolute value.
+    Bar,
+    Equals,
+    Semicolon,
+    Comma,
+    Question,
+    Colon,
+    Comparison(CompareOp),
+    /// The keywords of `let name = value in body`.
+    Let,
+    In,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Decimal { mantissa, scale } => {
+                let digits = format!("{:0>1$}", mantissa, *scale as usize + 1);
+                let (whole, fraction) = digits.split_at(digits.len() - *scale as usize);
+                write!(f, "{}.{}", whole, fraction)
+            }
+            Token::Identifier(name) => write!(f, "{}", name),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Times => write!(f, "*"),
+            Token::Divide => write!(f, "/"),
+            Token::Modulo | Token::Percent => write!(f, "%"),
+            Token::Power => write!(f, "**"),
+            Token::Bang => write!(f, "!"),
+            Token::LeftParen => write!(f, "("),
+            Token::RightParen => write!(f, ")"),
+            Token::LeftBracket => write!(f, "["),
+            Token::RightBracket => write!(f, "]"),
+            Token::LeftBrace => write!(f, "{{"),
+            Token::RightBrace => write!(f, "}}"),
+            Token::Bar => write!(f, "|"),
+            Token::Equals => write!(f, "="),
+            Token::Semicolon => write!(f, ";"),
+            Token::Comma => write!(f, ","),
+            Token::Question => write!(f, "?"),
+            Token::Colon => write!(f, ":"),
+            Token::Comparison(op) => write!(f, "{}", op.symbol()),
+            Token::Let => write!(f, "let"),
+            Token::In => write!(f, "in"),
+        }
+    }
+}
+
+/// The exact division `mantissa / 10^scale` in lowest terms, or `None` if
+/// either side is out of `i64` range.
+pub(crate) fn decimal_expression(mantissa: u64, scale: u32) -> Option<Expression> {
+    let mut num = mantissa;
+    let mut den = 10u64.checked_pow(scale)?;
+    let (mut a, mut b) = (num, den);
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    num /= a;
+    den /= a;
+    Some(Expression::Division(
+        Box::new(Expression::Number(i64::try_from(num).ok()?)),
+        Box::new(Expression::Number(i64::try_from(den).ok()?)),
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidCharacter {
+        ch: char,
+        pos: usize,
+    },
+    InvalidNumber {
+        pos: usize,
+    },
+    NumberTooLarge {
+        literal: String,
+        pos: usize,
+    },
+    /// `found` is `None` at the end of the input.
+    Expected {
+        expected: Token,
+        found: Option<Token>,
+        pos: usize,
+    },
+    EmptyParentheses {
+        pos: usize,
+    },
+    /// A group closed by the wrong kind of bracket, as in `[2 + 3)`. `pos`
+    /// is that of the closing bracket.
+    MismatchedBracket {
+        open: Token,
+        found: Token,
+        pos: usize,
+    },
+    ExpectedOperand {
+        operator: Token,
+        pos: usize,
+    },
+    /// A closing bracket with no opening one before it, as in `1 + 2)`, or a
+    /// `|` after an operand with no bar open, as in `1 + 2|`.
+    UnmatchedBracket {
+        found: Token,
+        pos: usize,
+    },
+    InvalidExpression {
+        pos: usize,
+    },
+    /// A second comparison operator in a row, as in `1 < 2 < 3`.
+    ChainedComparison {
+        pos: usize,
+    },
+    /// Only a bare variable name can be assigned to.
+    InvalidAssignmentTarget {
+        pos: usize,
+    },
+    UnexpectedToken {
+        token: Token,
+        pos: usize,
+    },
+    WrongArity {
+        operator: Token,
+        found: usize,
+        pos: usize,
+    },
+    /// Postfix input that leaves `count` values instead of one, as in
+    /// `1 2 3 +`. `pos` is where the second of them starts.
+    LeftoverOperands {
+        count: usize,
+        pos: usize,
+    },
+}
+
+impl ParseError {
+    pub fn pos(&self) -> usize {
+        match self {
+            ParseError::InvalidCharacter { pos, .. }
+            | ParseError::InvalidNumber { pos }
+            | ParseError::NumberTooLarge { pos, .. }
+            | ParseError::Expected { pos, .. }
+            | ParseError::EmptyParentheses { pos }
+            | ParseError::MismatchedBracket { pos, .. }
+            | ParseError::ExpectedOperand { pos, .. }
+            | ParseError::UnmatchedBracket { pos, .. }
+            | ParseError::InvalidExpression { pos }
+            | ParseError::ChainedComparison { pos }
+            | ParseError::InvalidAssignmentTarget { pos }
+            | ParseError::UnexpectedToken { pos, .. }
+            | ParseError::WrongArity { pos, .. }
+            | ParseError::LeftoverOperands { pos, .. } => *pos,
+        }
+    }
+}
+
+impl ParseError {
+    /// 1-based line and column of the error in `input`, the source it was
+    /// parsed from. Columns count characters, not bytes.
+    pub fn line_column(&self, input: &str) -> (usize, usize) {
+        let before = &input[..self.pos().min(input.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line = before.matches('\n').count() + 1;
+        (line, before[line_start..].chars().count() + 1)
+    }
+
+    /// Like `Display`, but locating the error by line and column in `input`,
+    /// which reads better for multi-line sources.
+    pub fn describe_in(&self, input: &str) -> String {
+        let (line, column) = self.line_column(input);
+        let mut out = String::new();
+        self.write_message(&mut out)
+            .expect("writing to a String cannot fail");
+        format!("{} at line {}, column {}", out, line, column)
+    }
+
+    fn write_message<W: fmt::Write>(&self, f: &mut W) -> fmt::Result {
+        match self {
+            ParseError::InvalidCharacter { ch, .. } => write!(f, "Invalid character: '{}'", ch)?,
+            ParseError::InvalidNumber { .. } => write!(f, "Invalid number")?,
+            ParseError::NumberTooLarge { literal, .. } => {
+                write!(f, "Number too large: '{}'", literal)?
+            }
+            ParseError::Expected {
+                expected, found, ..
+            } => match found {
+                Some(found) => write!(f, "Expected '{}', found '{}'", expected, found)?,
+                None => write!(f, "Expected '{}', found end of input", expected)?,
+            },
+            ParseError::EmptyParentheses { .. } => write!(f, "Empty parentheses")?,
+            ParseError::MismatchedBracket { open, found, .. } => {
+                write!(f, "Mismatched bracket: '{}' closed by '{}'", open, found)?
+            }
+            ParseError::ExpectedOperand { operator, .. } => {
+                write!(f, "Expected an operand after '{}'", operator)?
+            }
+            ParseError::UnmatchedBracket { found, .. } => {
+                write!(f, "Unmatched closing '{}'", found)?
+            }
+            ParseError::InvalidExpression { .. } => write!(f, "Invalid expression")?,
+            ParseError::ChainedComparison { .. } => write!(
+                f,
+                "Comparisons cannot be chained; combine them with parentheses"
+            )?,
+            ParseError::InvalidAssignmentTarget { .. } => write!(f, "Invalid assignment target")?,
+            ParseError::UnexpectedToken { token, .. } => {
+                write!(f, "Unexpected token: '{}'", token)?
+            }
+            ParseError::WrongArity {
+                operator, found, ..
+            } => write!(f, "Wrong number of operands for '{}': {}", operator, found)?,
+            ParseError::LeftoverOperands { count, .. } => {
+                write!(f, "{} values left on the stack, expected one", count)?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_message(f)?;
+        write!(f, " at position {}", self.pos())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexerConfig {
+    /// Extra characters accepted as `*`, such as `·`. Empty by default
+    /// so letters like `x` remain available for variable names.
+    pub multiplication_glyphs: Vec<char>,
+    /// Business-calculator mode: `%` becomes a postfix percent of the other
+    /// operand, so `200 + 10%` is 220, and the remainder operator is then
+    /// unavailable.
+    pub percent: bool,
+    /// Longest run of digits read for one part of a number literal. A longer
+    /// run fails as `NumberTooLarge` as soon as the limit is passed, so a
+    /// pasted megabyte of digits is never copied.
+    pub max_digits: usize,
+}
+
+impl Default for LexerConfig {
+    fn default() -> Self {
+        LexerConfig {
+            multiplication_glyphs: Vec::new(),
+            percent: false,
+            max_digits: 64,
+        }
+    }
+}
+
+/// Splits `input` into tokens, dropping their positions.
+///
+/// # Examples
+///
+/// ```
+/// use arvore_sintatica::{Token, lex};
+///
+/// assert_eq!(
+///     lex("1 + 2"),
+///     Ok(vec![Token::Number(1), Token::Plus, Token::Number(2)])
+/// );
+/// ```
+pub fn lex(input: &str) -> Result<Vec<Token>, ParseError> {
+    let tokens = lex_with_positions(input)?;
+    Ok(tokens.into_iter().map(|(token, _)| token).collect())
+}
+
+pub fn lex_with_positions(input: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    Lexer::new(input).collect()
+}
+
+/// Like `lex_with_positions`, with the byte range of each token's source
+/// text, so `12 + 345` gives `0..2`, `3..4` and `5..8`.
+pub fn lex_with_spans(input: &str) -> Result<Vec<(Token, Range<usize>)>, ParseError> {
+    let mut lexer = Lexer::new(input);
+    iter::from_fn(|| lexer.next_spanned()).collect()
+}
+
+/// Yields tokens one at a time, together with their byte offsets in the
+/// input. Iteration stops after the first error.
+pub struct Lexer<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    config: LexerConfig,
+    failed: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self::with_config(input, &LexerConfig::default())
+    }
+
+    pub fn with_config(input: &'a str, config: &LexerConfig) -> Self {
+        Lexer {
+            input,
+            chars: input.char_indices().peekable(),
+            config: config.clone(),
+            failed: false,
+        }
+    }
+
+    fn read_token(&mut self, pos: usize, c: char) -> Result<Token, ParseError> {
+        let token = match c {
+            c if self.config.multiplication_glyphs.contains(&c) => Token::Times,
+            '+' => Token::Plus,
+            // Unicode operators, as pasted from documents: `−` (U+2212),
+            // `×`, `÷` and the division slash `∕` (U+2215).
+            '-' | '−' => Token::Minus,
+            '×' => Token::Times,
+            '÷' | '∕' => Token::Divide,
+            // `**` is one token; `* *` stays two multiplications in a row.
+            '*' => {
+                self.chars.next();
+                if let Some(&(_, '*')) = self.chars.peek() {
+                    self.chars.next();
+                    return Ok(Token::Power);
+                }
+                return Ok(Token::Times);
+            }
+            '/' => Token::Divide,
+            '%' if self.config.percent => Token::Percent,
+            '%' => Token::Modulo,
+            // `<=`, `>=`, `==` and `!=` are single tokens.
+            '<' | '>' | '=' | '!' => {
+                self.chars.next();
+                let equals = self.chars.next_if(|&(_, c)| c == '=').is_some();
+                return Ok(match (c, equals) {
+                    ('<', false) => Token::Comparison(CompareOp::Less),
+                    ('<', true) => Token::Comparison(CompareOp::LessEqual),
+                    ('>', false) => Token::Comparison(CompareOp::Greater),
+                    ('>', true) => Token::Comparison(CompareOp::GreaterEqual),
+                    ('=', false) => Token::Equals,
+                    ('=', true) => Token::Comparison(CompareOp::Equal),
+                    (_, false) => Token::Bang,
+                    (_, true) => Token::Comparison(CompareOp::NotEqual),
+                });
+            }
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '[' => Token::LeftBracket,
+            ']' => Token::RightBracket,
+            '{' => Token::LeftBrace,
+            '}' => Token::RightBrace,
+            '|' => Token::Bar,
+            ';' => Token::Semicolon,
+            ',' => Token::Comma,
+            '?' => Token::Question,
+            ':' => Token::Colon,
+            '0'..='9' | '.' => return self.read_number(pos),
+            c if c.is_ascii_alphabetic() || c == '_' => return Ok(self.read_identifier()),
+            _ => return Err(ParseError::InvalidCharacter { ch: c, pos }),
+        };
+        self.chars.next();
+        Ok(token)
+    }
+
+    fn read_number(&mut self, pos: usize) -> Result<Token, ParseError> {
+        let digits = self.read_digits(pos)?;
+        // Either side of a decimal point may be empty, as in `5.` and `.5`,
+        // but not both: a lone `.` is not a number. Trailing zeros of the
+        // fraction don't change the value, so `5.0` is still an integer.
+        let mut fraction = String::new();
+        if let Some(&(_, '.')) = self.chars.peek() {
+            self.chars.next();
+            fraction = self.read_digits(pos)?;
+            if digits.is_empty() && fraction.is_empty() {
+                return Err(ParseError::InvalidCharacter { ch: '.', pos });
+            }
+        }
+        let fraction = fraction.trim_end_matches('0');
+        // An `e` right after the digits always starts an exponent, so `2e` is
+        // a malformed number rather than `2` followed by a variable `e`; write
+        // `2 * e` for that. A negative exponent moves the decimal point left,
+        // so `5e-1` is the fraction `.5`.
+        let mut exponent = None;
+        let mut negative_exponent = false;
+        if let Some(&(_, 'e' | 'E')) = self.chars.peek() {
+            self.chars.next();
+            if let Some(&(_, sign @ ('+' | '-'))) = self.chars.peek() {
+                self.chars.next();
+                negative_exponent = sign == '-';
+            }
+            let digits = self.read_digits(pos)?;
+            if digits.is_empty() {
+                return Err(ParseError::InvalidNumber { pos });
+            }
+            exponent = Some(digits);
+        }
+
+        // Only well-formed literals get this far, so any failure to fit in
+        // a `u64` is a range problem.
+        let too_large = || ParseError::NumberTooLarge {
+            literal: self.literal_from(pos).to_string(),
+            pos,
+        };
+        // The fraction's digits carry on the mantissa, each one a place
+        // the exponent has to make up before the literal is an integer.
+        let digits = format!("{}{}", digits, fraction);
+        let mut num = match digits.trim_start_matches('0') {
+            "" => return Ok(Token::Number(0)),
+            digits => digits.parse::<u64>().map_err(|_| too_large())?,
+        };
+        let exp = match exponent {
+            Some(exponent) => i64::from(exponent.parse::<u32>().map_err(|_| too_large())?),
+            None => 0,
+        };
+        let exp = if negative_exponent { -exp } else { exp };
+        let shift = exp - fraction.len() as i64;
+        if shift >= 0 {
+            return u32::try_from(shift)
+                .ok()
+                .and_then(|shift| 10u64.checked_pow(shift))
+                .and_then(|scale| num.checked_mul(scale))
+                .map(Token::Number)
+                .ok_or_else(too_large);
+        }
+        // Trailing zeros of the mantissa, as in `50e-1`, cancel places of
+        // the scale, so the literal may still turn out to be an integer.
+        let mut scale = u32::try_from(-shift).map_err(|_| too_large())?;
+        while scale > 0 && num % 10 == 0 {
+            num /= 10;
+            scale -= 1;
+        }
+        if scale == 0 {
+            return Ok(Token::Number(num));
+        }
+        10u64.checked_pow(scale).ok_or_else(too_large)?;
+        Ok(Token::Decimal {
+            mantissa: num,
+            scale,
+        })
+    }
+
+    // Reads a run of ASCII digits of the literal starting at `pos`. A run
+    // longer than `max_digits` fails as soon as the limit is passed, leaving
+    // the rest unread and out of the error message.
+    fn read_digits(&mut self, pos: usize) -> Result<String, ParseError> {
+        let mut digits = String::new();
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !c.is_ascii_digit() {
+                break;
+            }
+            if digits.len() == self.config.max_digits {
+                return Err(ParseError::NumberTooLarge {
+                    literal: format!("{}…", self.literal_from(pos)),
+                    pos,
+                });
+            }
+            digits.push(c);
+            self.chars.next();
+        }
+        Ok(digits)
+    }
+
+    // The input from `pos` up to the next unread character.
+    fn literal_from(&self, pos: usize) -> &'a str {
+        &self.input[pos..self.offset()]
+    }
+
+    // The byte offset of the next unread character.
+    fn offset(&self) -> usize {
+        self.chars
+            .clone()
+            .next()
+            .map_or(self.input.len(), |(offset, _)| offset)
+    }
+
+    /// The next token with the byte range it was read from, as `next` does
+    /// with just the start.
+    pub fn next_spanned(&mut self) -> Option<Result<(Token, Range<usize>), ParseError>> {
+        if self.failed {
+            return None;
+        }
+        while let Some(&(_, ' ' | '\t' | '\n' | '\r')) = self.chars.peek() {
+            self.chars.next();
+        }
+        let &(pos, c) = self.chars.peek()?;
+        let result = self
+            .read_token(pos, c)
+            .map(|token| (token, pos..self.offset()));
+        self.failed = result.is_err();
+        Some(result)
+    }
+
+    fn read_identifier(&mut self) -> Token {
+        let chars = &mut self.chars;
+        let mut name = String::new();
+        while let Some(&(_, c)) = chars.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                name.push(c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        // `mod` is reserved as a spelling of the remainder operator, which
+        // stays available in percent mode this way.
+        match name.as_str() {
+            "mod" => Token::Modulo,
+            "let" => Token::Let,
+            "in" => Token::In,
+            _ => Token::Identifier(name),
+        }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<(Token, usize), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_spanned()?;
+        Some(result.map(|(token, span)| (token, span.start)))
+    }
+}
+
+impl Parser {
+    pub fn new(input: &str) -> Result<Self, ParseError> {
+        Self::with_config(input, &LexerConfig::default())
+    }
+
+    pub fn with_config(input: &str, config: &LexerConfig) -> Result<Self, ParseError> {
+        let tokens = Self::tokenize(input, config)?;
+        Ok(Parser {
+            tokens,
+            pos: 0,
+            end: input.len(),
+            open_bars: 0,
+        })
+    }
+
+    fn tokenize(input: &str, config: &LexerConfig) -> Result<Vec<(Token, usize)>, ParseError> {
+        Lexer::with_config(input, config).collect()
+    }
+
+    fn current(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn current_pos(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |&(_, pos)| pos)
+    }
+
+    fn advance(&mut self) {
+        self.pos += 1;
+    }
+
+    pub fn parse(&mut self) -> Result<Expression, ParseError> {
+        let expr = self.parse_expression()?;
+        self.expect_end()?;
+        Ok(expr)
+    }
+
+    /// Parses one expression and stops at the first token that cannot
+    /// continue it, leaving that token and the rest in the buffer.
+    pub fn parse_partial(&mut self) -> Result<Expression, ParseError> {
+        self.parse_expression()
+    }
+
+    pub fn remaining_tokens(&self) -> &[(Token, usize)] {
+        &self.tokens[self.pos.min(self.tokens.len())..]
+    }
+
+    /// Byte offset in the input of the next unconsumed token, or the input
+    /// length once everything has been consumed.
+    pub fn offset(&self) -> usize {
+        self.current_pos()
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        match self.current() {
+            Some(token) if *token == expected => {
+                self.advance();
+                Ok(())
+            }
+            found => Err(ParseError::Expected {
+                expected,
+                found: found.cloned(),
+                pos: self.current_pos(),
+            }),
+        }
+    }
+
+    fn expect_end(&mut self) -> Result<(), ParseError> {
+        match self.current() {
+            Some(_) => Err(self.unexpected_token()),
+            None => Ok(()),
+        }
+    }
+
+    // The error for a current token that cannot appear where it is.
+    fn unexpected_token(&self) -> ParseError {
+        let token = self.current().cloned().expect("a current token");
+        let pos = self.current_pos();
+        let unmatched = match token {
+            Token::RightParen | Token::RightBracket | Token::RightBrace => self.open_groups() == 0,
+            Token::Bar => self.open_bars == 0,
+            _ => false,
+        };
+        if unmatched {
+            return ParseError::UnmatchedBracket { found: token, pos };
+        }
+        ParseError::UnexpectedToken { token, pos }
+    }
+
+    // How many brackets before the current token are still open. Only
+    // consulted on the error path, so it simply rescans.
+    fn open_groups(&self) -> usize {
+        let mut open = 0usize;
+        for (token, _) in &self.tokens[..self.pos.min(self.tokens.len())] {
+            match token {
+                Token::LeftParen | Token::LeftBracket | Token::LeftBrace => open += 1,
+                Token::RightParen | Token::RightBracket | Token::RightBrace => {
+                    open = open.saturating_sub(1)
+                }
+                _ => {}
+            }
+        }
+        open
+    }
+
+    pub fn parse_program(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let mut statements = Vec::new();
+        loop {
+            while let Some(Token::Semicolon) = self.current() {
+                self.advance();
+            }
+            if self.current().is_none() {
+                return Ok(statements);
+            }
+            statements.push(self.parse_statement()?);
+            match self.current() {
+                Some(Token::Semicolon) | None => {}
+                Some(_) => return Err(self.unexpected_token()),
+            }
+        }
+    }
+
+    // Assignments nest to the right, so `a = b = 5` is `a = (b = 5)`.
+    fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        if let Some(Token::Identifier(name)) = self.current()
+            && let Some((Token::Equals, _)) = self.tokens.get(self.pos + 1)
+        {
+            let name = name.clone();
+            self.pos += 2;
+            let value = self.parse_operand(Token::Equals, Self::parse_statement)?;
+            return Ok(Statement::Assignment {
+                name,
+                value: Box::new(value),
+            });
+        }
+        let target_pos = self.current_pos();
+        let expr = self.parse_expression()?;
+        if let Some(Token::Equals) = self.current() {
+            return Err(ParseError::InvalidAssignmentTarget { pos: target_pos });
+        }
+        Ok(Statement::Expression(expr))
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, ParseError> {
+        self.parse_conditional()
+    }
+
+    // `cond ? then : els` binds loosest of all and nests to the right, so
+    // `a ? b : c ? d : e` is `a ? b : (c ? d : e)`.
+    fn parse_conditional(&mut self) -> Result<Expression, ParseError> {
+        let cond = self.parse_comparison()?;
+        if let Some(Token::Question) = self.current() {
+            self.advance();
+            let then = self.parse_operand(Token::Question, Self::parse_conditional)?;
+            self.expect(Token::Colon)?;
+            let els = self.parse_operand(Token::Colon, Self::parse_conditional)?;
+            return Ok(Expression::Conditional {
+                cond: Box::new(cond),
+                then: Box::new(then),
+                els: Box::new(els),
+            });
+        }
+        Ok(cond)
+    }
+
+    // At most one comparison: `1 < 2 < 3` is rejected rather than read as
+    // `(1 < 2) < 3`, which compares a truth value with 3.
+    fn parse_comparison(&mut self) -> Result<Expression, ParseError> {
+        let left = self.parse_binary(0)?;
+        let Some(Token::Comparison(op)) = self.current() else {
+            return Ok(left);
+        };
+        let op = *op;
+        self.advance();
+        let right = self.parse_operand(Token::Comparison(op), |p| p.parse_binary(0))?;
+        if let Some(Token::Comparison(_)) = self.current() {
+            return Err(ParseError::ChainedComparison {
+                pos: self.current_pos(),
+            });
+        }
+        Ok(Expression::Comparison {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+
+    // Precedence climbing over `BinOp::precedence`: operators binding at
+    // least as tightly as `min_prec` are folded into `left`, and the right
+    // operand is parsed one level higher for left-associative operators.
+    //
+    // A percent applies to the operand it follows, relative to the operand on
+    // the other side: `a + b%` and `a - b%` add or take away `b` percent of
+    // `a`, `a * b%` is `a * b / 100` and `a / b%` is `a * 100 / b`. Elsewhere,
+    // as in a lone `b%`, it means `b / 100`. The tree holds the expanded
+    // arithmetic, so `a` appears twice in `a + b%`.
+    fn parse_binary(&mut self, min_prec: u8) -> Result<Expression, ParseError> {
+        let mut left = self.parse_factor()?;
+        // Right operands are parsed above level 0 and leave their percent to
+        // the operator they belong to.
+        if min_prec == 0 && self.current() == Some(&Token::Percent) {
+            self.advance();
+            left = Expression::binary(BinOp::Div, left, Expression::Number(100));
+        }
+
+        while let Some((op, consumes_token)) = self.binary_operator() {
+            if op.precedence() < min_prec {
+                break;
+            }
+            let operator = self.current().cloned();
+            if consumes_token {
+                self.advance();
+            }
+            let next_min = match op.associativity() {
+                Associativity::Left => op.precedence() + 1,
+                Associativity::Right => op.precedence(),
+            };
+            let right = match operator {
+                Some(operator) if consumes_token => {
+                    self.parse_operand(operator, |parser| parser.parse_binary(next_min))?
+                }
+                _ => self.parse_binary(next_min)?,
+            };
+            left = if let Some(Token::Percent) = self.current() {
+                self.advance();
+                Self::percent_of(op, left, right)
+            } else {
+                Expression::binary(op, left, right)
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn percent_of(op: BinOp, left: Expression, right: Expression) -> Expression {
+        let hundred = Expression::Number(100);
+        match op {
+            BinOp::Add | BinOp::Sub => {
+                let share = Expression::binary(
+                    BinOp::Div,
+                    Expression::binary(BinOp::Mul, left.clone(), right),
+                    hundred,
+                );
+                Expression::binary(op, left, share)
+            }
+            BinOp::Mul => Expression::binary(
+                BinOp::Div,
+                Expression::binary(BinOp::Mul, left, right),
+                hundred,
+            ),
+            BinOp::Div => Expression::binary(
+                BinOp::Div,
+                Expression::binary(BinOp::Mul, left, hundred),
+                right,
+            ),
+            _ => Expression::binary(op, left, Expression::binary(BinOp::Div, right, hundred)),
+        }
+    }
+
+    fn binary_operator(&self) -> Option<(BinOp, bool)> {
+        match self.current()? {
+            Token::Plus => Some((BinOp::Add, true)),
+            Token::Minus => Some((BinOp::Sub, true)),
+            Token::Times => Some((BinOp::Mul, true)),
+            Token::Divide => Some((BinOp::Div, true)),
+            Token::Modulo => Some((BinOp::Rem, true)),
+            // Implicit multiplication, as in `2(3 + 4)` or `(1 + 2)[3 + 4]`.
+            // Only an opening bracket triggers it: `2 3` stays an error.
+            Token::LeftParen | Token::LeftBracket | Token::LeftBrace => Some((BinOp::Mul, false)),
+            _ => None,
+        }
+    }
+
+    // Runs `parse` for the operand of `operator`, reporting a missing operand
+    // (as in `(1 +)`) instead of a generic invalid expression.
+    fn parse_operand<T>(
+        &mut self,
+        operator: Token,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let operand_pos = self.current_pos();
+        parse(self).map_err(|e| match e {
+            ParseError::InvalidExpression { pos }
+            | ParseError::UnexpectedToken { pos, .. }
+            | ParseError::UnmatchedBracket { pos, .. }
+                if pos == operand_pos =>
+            {
+                ParseError::ExpectedOperand { operator, pos }
+            }
+            e => e,
+        })
+    }
+
+    fn parse_factor(&mut self) -> Result<Expression, ParseError> {
+        match self.current() {
+            // A sign with nothing after it, before a `;` or before a closing
+            // bracket is a stray operator rather than the start of an operand.
+            Some(Token::Plus | Token::Minus)
+                if matches!(
+                    self.tokens.get(self.pos + 1),
+                    None | Some((
+                        Token::Semicolon
+                            | Token::RightParen
+                            | Token::RightBracket
+                            | Token::RightBrace,
+                        _
+                    ))
+                ) =>
+            {
+                Err(self.unexpected_token())
+            }
+            Some(Token::Minus) => {
+                self.advance();
+                // `i64::MIN` has no positive counterpart, so its magnitude is
+                // folded with the minus into one literal. Any other negative
+                // literal stays a `Negation` node.
+                if let Some(&(Token::Number(n), _)) = self.tokens.get(self.pos)
+                    && n == i64::MIN.unsigned_abs()
+                    && !matches!(
+                        self.tokens.get(self.pos + 1),
+                        Some((Token::Bang | Token::Power, _))
+                    )
+                {
+                    self.advance();
+                    return Ok(Expression::Number(i64::MIN));
+                }
+                let expr = self.parse_operand(Token::Minus, Self::parse_factor)?;
+                Ok(Expression::Negation(Box::new(expr)))
+            }
+            // Unary plus is accepted but leaves no trace in the tree.
+            Some(Token::Plus) => {
+                self.advance();
+                self.parse_operand(Token::Plus, Self::parse_factor)
+            }
+            // Nothing can start with these, so the left operand was probably
+            // forgotten.
+            Some(
+                Token::Times
+                | Token::Divide
+                | Token::Modulo
+                | Token::Percent
+                | Token::Power
+                | Token::Bang
+                | Token::Question
+                | Token::Colon
+                | Token::Equals
+                | Token::In
+                | Token::Comparison(_),
+            ) => Err(self.unexpected_token()),
+            Some(Token::RightParen | Token::RightBracket | Token::RightBrace)
+                if self.open_groups() == 0 =>
+            {
+                Err(self.unexpected_token())
+            }
+            _ => self.parse_power(),
+        }
+    }
+
+    // `**` is handled here rather than in `parse_binary` so that it binds
+    // tighter than a unary minus on its left but still accepts one on its
+    // right, as in `-2 ** -1`. Parsing the exponent as a factor also makes
+    // it right-associative.
+    fn parse_power(&mut self) -> Result<Expression, ParseError> {
+        let base = self.parse_postfix()?;
+        if let Some(Token::Power) = self.current() {
+            self.advance();
+            let exponent = self.parse_operand(Token::Power, Self::parse_factor)?;
+            return Ok(Expression::Power(Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.parse_primary()?;
+
+        while let Some(Token::Bang) = self.current() {
+            self.advance();
+            expr = Expression::Factorial(Box::new(expr));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, ParseError> {
+        match self.current() {
+            Some(Token::Number(n)) => {
+                let num = i64::try_from(*n).map_err(|_| ParseError::NumberTooLarge {
+                    literal: n.to_string(),
+                    pos: self.current_pos(),
+                })?;
+                self.advance();
+                Ok(Expression::Number(num))
+            }
+            Some(&Token::Decimal { mantissa, scale }) => {
+                let expr = decimal_expression(mantissa, scale).ok_or_else(|| {
+                    ParseError::NumberTooLarge {
+                        literal: Token::Decimal { mantissa, scale }.to_string(),
+                        pos: self.current_pos(),
+                    }
+                })?;
+                self.advance();
+                Ok(expr)
+            }
+            Some(Token::Identifier(name)) => {
+                let name = name.clone();
+                self.advance();
+                if let Some(Token::LeftParen) = self.current() {
+                    self.advance();
+                    let args = self.parse_arguments()?;
+                    return Ok(Expression::Call { name, args });
+                }
+                Ok(Expression::Variable(name))
+            }
+            // A `|` where an operand is expected opens an absolute value, and
+            // one after an operand closes it, so `||a| - |b||` nests the way
+            // it reads.
+            Some(Token::Bar) => {
+                self.advance();
+                self.open_bars += 1;
+                let expr = self.parse_operand(Token::Bar, Self::parse_expression)?;
+                self.expect(Token::Bar)?;
+                self.open_bars -= 1;
+                Ok(Expression::call("abs", vec![expr]))
+            }
+            // The body extends as far right as it can, like the branches of a
+            // conditional: `let x = 2 in x + 1` is `let x = 2 in (x + 1)`.
+            Some(Token::Let) => {
+                self.advance();
+                let Some(Token::Identifier(name)) = self.current() else {
+                    return Err(ParseError::InvalidAssignmentTarget {
+                        pos: self.current_pos(),
+                    });
+                };
+                let name = name.clone();
+                self.advance();
+                self.expect(Token::Equals)?;
+                let value = self.parse_operand(Token::Equals, Self::parse_expression)?;
+                self.expect(Token::In)?;
+                let body = self.parse_operand(Token::In, Self::parse_expression)?;
+                Ok(Expression::let_in(&name, value, body))
+            }
+            Some(open @ (Token::LeftParen | Token::LeftBracket | Token::LeftBrace)) => {
+                let open = open.clone();
+                let close = match open {
+                    Token::LeftParen => Token::RightParen,
+                    Token::LeftBracket => Token::RightBracket,
+                    _ => Token::RightBrace,
+                };
+                if self
+                    .tokens
+                    .get(self.pos + 1)
+                    .is_some_and(|(t, _)| *t == close)
+                {
+                    return Err(ParseError::EmptyParentheses {
+                        pos: self.current_pos(),
+                    });
+                }
+                self.advance();
+                let expr = self.parse_expression()?;
+                match self.current() {
+                    Some(found @ (Token::RightParen | Token::RightBracket | Token::RightBrace))
+                        if *found != close =>
+                    {
+                        Err(ParseError::MismatchedBracket {
+                            open,
+                            found: found.clone(),
+                            pos: self.current_pos(),
+                        })
+                    }
+                    _ => {
+                        self.expect(close)?;
+                        Ok(expr)
+                    }
+                }
+            }
+            _ => Err(ParseError::InvalidExpression {
+                pos: self.current_pos(),
+            }),
+        }
+    }
+
+    // Parses `arg, arg, ...)` after the opening parenthesis of a call.
+    fn parse_arguments(&mut self) -> Result<Vec<Expression>, ParseError> {
+        let mut args = Vec::new();
+        if let Some(Token::RightParen) = self.current() {
+            self.advance();
+            return Ok(args);
+        }
+        loop {
+            args.push(self.parse_expression()?);
+            if let Some(Token::Comma) = self.current() {
+                self.advance();
+                continue;
+            }
+            self.expect(Token::RightParen)?;
+            return Ok(args);
+        }
+    }
+
+    fn parse_sexpr(&mut self) -> Result<Expression, ParseError> {
+        match self.current() {
+            Some(Token::LeftParen) => {
+                self.advance();
+                let op_pos = self.current_pos();
+                // `(let name value body)`, the name written as a variable.
+                if let Some(Token::Let) = self.current() {
+                    self.advance();
+                    let Some(Token::Identifier(name)) = self.current() else {
+                        return Err(ParseError::InvalidAssignmentTarget {
+                            pos: self.current_pos(),
+                        });
+                    };
+                    let name = name.clone();
+                    self.advance();
+                    let value = self.parse_sexpr()?;
+                    let body = self.parse_sexpr()?;
+                    self.expect(Token::RightParen)?;
+                    return Ok(Expression::let_in(&name, value, body));
+                }
+                let operator = match self.current() {
+                    Some(
+                        token @ (Token::Plus
+                        | Token::Minus
+                        | Token::Times
+                        | Token::Divide
+                        | Token::Modulo
+                        | Token::Power
+                        | Token::Bang
+                        | Token::Question
+                        | Token::Comparison(_)
+                        | Token::Identifier(_)),
+                    ) => token.clone(),
+                    _ => return Err(ParseError::InvalidExpression { pos: op_pos }),
+                };
+                self.advance();
+
+                let mut operands = Vec::new();
+                while !matches!(self.current(), Some(Token::RightParen) | None) {
+                    operands.push(self.parse_sexpr()?);
+                }
+                self.expect(Token::RightParen)?;
+
+                Self::build_sexpr_node(operator, operands, op_pos)
+            }
+            // A bare `-` right before a number is a negative literal, which is
+            // how `to_sexpr` prints `Number(n)` for n < 0.
+            Some(Token::Minus) => match self.tokens.get(self.pos + 1) {
+                Some(&(Token::Number(n), pos)) => {
+                    let num =
+                        0i64.checked_sub_unsigned(n)
+                            .ok_or_else(|| ParseError::NumberTooLarge {
+                                literal: format!("-{}", n),
+                                pos,
+                            })?;
+                    self.pos += 2;
+                    Ok(Expression::Number(num))
+                }
+                _ => Err(ParseError::InvalidExpression {
+                    pos: self.current_pos(),
+                }),
+            },
+            // Not `parse_primary`: a name followed by `(` is the next operand
+            // here, not a call.
+            Some(Token::Identifier(name)) => {
+                let name = name.clone();
+                self.advance();
+                Ok(Expression::Variable(name))
+            }
+            Some(Token::Number(_) | Token::Decimal { .. }) => self.parse_primary(),
+            _ => Err(ParseError::InvalidExpression {
+                pos: self.current_pos(),
+            }),
+        }
+    }
+
+    pub(crate) fn build_sexpr_node(
+        operator: Token,
+        operands: Vec<Expression>,
+        pos: usize,
+    ) -> Result<Expression, ParseError> {
+        if let Token::Identifier(name) = operator {
+            return Ok(Expression::Call {
+                name,
+                args: operands,
+            });
+        }
+        let found = operands.len();
+        let mut operands = operands.into_iter().map(Box::new);
+        let mut next = || operands.next().expect("operand count checked");
+        let expr = match (&operator, found) {
+            (Token::Minus, 1) => Expression::Negation(next()),
+            (Token::Bang, 1) => Expression::Factorial(next()),
+            (Token::Plus, 2) => Expression::Addition(next(), next()),
+            (Token::Minus, 2) => Expression::Subtraction(next(), next()),
+            (Token::Times, 2) => Expression::Multiplication(next(), next()),
+            (Token::Divide, 2) => Expression::Division(next(), next()),
+            (Token::Modulo, 2) => Expression::Remainder(next(), next()),
+            (Token::Power, 2) => Expression::Power(next(), next()),
+            (Token::Comparison(op), 2) => Expression::Comparison {
+                op: *op,
+                left: next(),
+                right: next(),
+            },
+            (Token::Question, 3) => Expression::Conditional {
+                cond: next(),
+                then: next(),
+                els: next(),
+            },
+            _ => {
+                return Err(ParseError::WrongArity {
+                    operator,
+                    found,
+                    pos,
+                });
+            }
+        };
+        Ok(expr)
+    }
+}
+
+impl Expression {
+    pub fn from_sexpr(input: &str) -> Result<Expression, ParseError> {
+        let mut parser = Parser::new(input)?;
+        let expr = parser.parse_sexpr()?;
+        parser.expect_end()?;
+        Ok(expr)
+    }
+}
+
+/// Byte offsets of the opening brackets in `input` whose pair could be
+/// removed without changing how the program parses, as both pairs in
+/// `(2) + 3` and `((2 + 3))`, but not the one in `(2 + 3) * 4`. The
+/// parentheses of a call are never counted.
+pub fn redundant_parentheses(input: &str, config: &LexerConfig) -> Result<Vec<usize>, ParseError> {
+    let tokens = Parser::tokenize(input, config)?;
+    let parse = |tokens: Vec<(Token, usize)>| {
+        Parser {
+            tokens,
+            pos: 0,
+            end: input.len(),
+            open_bars: 0,
+        }
+        .parse_program()
+    };
+    let program = parse(tokens.clone())?;
+
+    // Indices of each grouping pair; a `(` right after a name opens a call.
+    let mut open = Vec::new();
+    let mut pairs = Vec::new();
+    for (i, (token, _)) in tokens.iter().enumerate() {
+        match token {
+            Token::LeftParen | Token::LeftBracket | Token::LeftBrace => {
+                let call = *token == Token::LeftParen
+                    && i > 0
+                    && matches!(tokens[i - 1].0, Token::Identifier(_));
+                open.push((i, call));
+            }
+            Token::RightParen | Token::RightBracket | Token::RightBrace => {
+                if let Some((start, false)) = open.pop() {
+                    pairs.push((start, i));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut redundant: Vec<usize> = pairs
+        .into_iter()
+        .filter(|&(start, end)| {
+            let without = tokens
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| i != start && i != end)
+                .map(|(_, token)| token.clone())
+                .collect();
+            parse(without).is_ok_and(|other| other == program)
+        })
+        .map(|(start, _)| tokens[start].1)
+        .collect();
+    redundant.sort_unstable();
+    Ok(redundant)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Expression, ParseError> {
+        Parser::new(input)?.parse()
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        assert_eq!(
+            parse("1 + 2 * 3"),
+            Ok(Expression::Addition(
+                Box::new(Expression::Number(1)),
+                Box::new(Expression::Multiplication(
+                    Box::new(Expression::Number(2)),
+                    Box::new(Expression::Number(3)),
+                )),
+            ))
+        );
+    }
+
+    #[test]
+    fn subtraction_is_left_associative() {
+        assert_eq!(
+            parse("1 - 2 - 3"),
+            Ok(Expression::sub(
+                Expression::sub(Expression::num(1), Expression::num(2)),
+                Expression::num(3),
+            ))
+        );
+    }
+
+    #[test]
+    fn implicit_multiplication_before_a_group() {
+        assert_eq!(parse("2(3+4)").unwrap().evaluate(), Ok(14));
+        assert_eq!(parse("(1+1)(2+2)").unwrap().evaluate(), Ok(8));
+    }
+
+    #[test]
+    fn configured_multiplication_glyph() {
+        // `×` itself is always accepted, so the option is shown with `·`.
+        let config = LexerConfig {
+            multiplication_glyphs: vec!['·'],
+            ..LexerConfig::default()
+        };
+        let expr = Parser::with_config("2 · 3", &config).and_then(|mut parser| parser.parse());
+        assert_eq!(
+            expr,
+            Ok(Expression::mul(Expression::num(2), Expression::num(3)))
+        );
+        assert_eq!(
+            parse("2 · 3"),
+            Err(ParseError::InvalidCharacter { ch: '·', pos: 2 })
+        );
+    }
+
+    #[test]
+    fn unary_plus() {
+        assert_eq!(parse("+5"), Ok(Expression::num(5)));
+        assert_eq!(
+            parse("3 * +2"),
+            Ok(Expression::mul(Expression::num(3), Expression::num(2)))
+        );
+        assert_eq!(parse("-+5"), Ok(Expression::neg(Expression::num(5))));
+    }
+
+    #[test]
+    fn sexpr_round_trip() {
+        for input in [
+            "2 * 3 + 4",
+            "-(1 + 2)",
+            "-5 - 3",
+            "2 ** 3 ** 2",
+            "max(1, 2) % 3!",
+            "1 < 2 ? x : -y",
+            "let x = 5 in x * x",
+        ] {
+            let expr = parse(input).unwrap();
+            assert_eq!(
+                Expression::from_sexpr(&expr.to_sexpr()),
+                Ok(expr),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn sexpr_arity_errors() {
+        let wrong_arity = |operator, found| ParseError::WrongArity {
+            operator,
+            found,
+            pos: 1,
+        };
+        assert_eq!(
+            Expression::from_sexpr("(+)"),
+            Err(wrong_arity(Token::Plus, 0))
+        );
+        assert_eq!(
+            Expression::from_sexpr("(+ 1)"),
+            Err(wrong_arity(Token::Plus, 1))
+        );
+        assert_eq!(
+            Expression::from_sexpr("(* 1 2 3)"),
+            Err(wrong_arity(Token::Times, 3))
+        );
+        assert_eq!(
+            Expression::from_sexpr("(- 1 2 3)"),
+            Err(wrong_arity(Token::Minus, 3))
+        );
+        assert_eq!(
+            Expression::from_sexpr("(! 1 2)"),
+            Err(wrong_arity(Token::Bang, 2))
+        );
+        assert_eq!(
+            Expression::from_sexpr("(? 1 2)"),
+            Err(wrong_arity(Token::Question, 2))
+        );
+    }
+
+    #[test]
+    fn scientific_notation() {
+        assert_eq!(lex("1e3"), Ok(vec![Token::Number(1000)]));
+        assert_eq!(parse("1E6 + 1").unwrap().evaluate(), Ok(1000001));
+        assert_eq!(lex("2e+2"), Ok(vec![Token::Number(200)]));
+        assert_eq!(lex("2e"), Err(ParseError::InvalidNumber { pos: 0 }));
+        assert_eq!(
+            lex("1e-3"),
+            Ok(vec![Token::Decimal {
+                mantissa: 1,
+                scale: 3,
+            }])
+        );
+        assert_eq!(
+            lex("1e100"),
+            Err(ParseError::NumberTooLarge {
+                literal: "1e100".to_string(),
+                pos: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn conditional_binds_looser_than_arithmetic() {
+        let n = Expression::num;
+        assert_eq!(
+            parse("1 + 1 ? 2 * 3 : 4 - 5"),
+            Ok(Expression::conditional(
+                Expression::add(n(1), n(1)),
+                Expression::mul(n(2), n(3)),
+                Expression::sub(n(4), n(5)),
+            ))
+        );
+        // Right-associative: the else branch holds the second conditional.
+        assert_eq!(
+            parse("0 ? 1 : 0 ? 2 : 3"),
+            Ok(Expression::conditional(
+                n(0),
+                n(1),
+                Expression::conditional(n(0), n(2), n(3)),
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_partial_leaves_the_rest() {
+        let input = "1 + 2 ; rest";
+        let mut parser = Parser::new(input).unwrap();
+        assert_eq!(
+            parser.parse_partial(),
+            Ok(Expression::add(Expression::num(1), Expression::num(2)))
+        );
+        assert_eq!(
+            parser.remaining_tokens(),
+            &[
+                (Token::Semicolon, 6),
+                (Token::Identifier("rest".to_string()), 8)
+            ]
+        );
+        assert_eq!(&input[parser.offset()..], "; rest");
+    }
+
+    #[test]
+    fn most_negative_literal() {
+        assert_eq!(parse("-9223372036854775808"), Ok(Expression::num(i64::MIN)));
+        assert_eq!(
+            parse("9223372036854775808"),
+            Err(ParseError::NumberTooLarge {
+                literal: "9223372036854775808".to_string(),
+                pos: 0,
+            })
+        );
+        // Postfix operators bind tighter than the sign, so the magnitude
+        // stays a separate literal there.
+        assert!(matches!(
+            parse("-9223372036854775808!"),
+            Err(ParseError::NumberTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn empty_groups() {
+        assert_eq!(parse("()"), Err(ParseError::EmptyParentheses { pos: 0 }));
+        assert_eq!(
+            parse("2 * ()"),
+            Err(ParseError::EmptyParentheses { pos: 4 })
+        );
+        assert_eq!(parse("(())"), Err(ParseError::EmptyParentheses { pos: 1 }));
+        assert_eq!(parse("[ ]"), Err(ParseError::EmptyParentheses { pos: 0 }));
+        // A call without arguments is not an empty group.
+        assert_eq!(parse("max()"), Ok(Expression::call("max", vec![])));
+    }
+
+    #[test]
+    fn decimal_literals() {
+        let half = || Expression::div(Expression::num(1), Expression::num(2));
+        assert_eq!(parse(".5"), Ok(half()));
+        assert_eq!(
+            parse("1.25"),
+            Ok(Expression::div(Expression::num(5), Expression::num(4)))
+        );
+        assert_eq!(parse("5."), Ok(Expression::num(5)));
+        assert_eq!(parse("5.0"), Ok(Expression::num(5)));
+        assert_eq!(parse(".5e1"), Ok(Expression::num(5)));
+        assert_eq!(parse(".5 + .5").unwrap().evaluate_rational(), Ok((1, 1)));
+        assert_eq!(parse("5. * 2").unwrap().evaluate(), Ok(10));
+        assert_eq!(
+            parse("."),
+            Err(ParseError::InvalidCharacter { ch: '.', pos: 0 })
+        );
+        assert_eq!(
+            lex("12.5"),
+            Ok(vec![Token::Decimal {
+                mantissa: 125,
+                scale: 1,
+            }])
+        );
+        assert_eq!(
+            Token::Decimal {
+                mantissa: 5,
+                scale: 2
+            }
+            .to_string(),
+            "0.05"
+        );
+        assert!(matches!(
+            parse("1.00000000000000000001"),
+            Err(ParseError::NumberTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn lexer_iterator_matches_lex() {
+        for input in ["", "1 + 2", "(12 * x) - max(3, 4)!", "  7   mod 2 ^ 3 "] {
+            let streamed: Result<Vec<Token>, ParseError> = Lexer::new(input)
+                .map(|item| item.map(|(token, _)| token))
+                .collect();
+            assert_eq!(streamed, lex(input), "input {:?}", input);
+        }
+
+        let mut lexer = Lexer::new("1 # 2");
+        assert_eq!(lexer.next(), Some(Ok((Token::Number(1), 0))));
+        assert_eq!(
+            lexer.next(),
+            Some(Err(ParseError::InvalidCharacter { ch: '#', pos: 2 }))
+        );
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn double_star_power() {
+        assert_eq!(
+            lex("2**3"),
+            Ok(vec![Token::Number(2), Token::Power, Token::Number(3)])
+        );
+        let expr = parse("2**3**2").unwrap();
+        assert_eq!(
+            expr,
+            Expression::pow(
+                Expression::num(2),
+                Expression::pow(Expression::num(3), Expression::num(2))
+            )
+        );
+        assert_eq!(expr.evaluate(), Ok(512));
+        assert_eq!(
+            parse("2* *3"),
+            Err(ParseError::ExpectedOperand {
+                operator: Token::Times,
+                pos: 3,
+            })
+        );
+        assert!(parse("2 * * 3").is_err());
+    }
+
+    #[test]
+    fn consecutive_operators() {
+        assert_eq!(
+            parse("2 + * 3"),
+            Err(ParseError::ExpectedOperand {
+                operator: Token::Plus,
+                pos: 4,
+            })
+        );
+        assert_eq!(
+            parse("2 */ 3"),
+            Err(ParseError::ExpectedOperand {
+                operator: Token::Times,
+                pos: 3,
+            })
+        );
+        assert_eq!(
+            parse("2 + -3"),
+            Ok(Expression::add(
+                Expression::num(2),
+                Expression::neg(Expression::num(3))
+            ))
+        );
+    }
+
+    #[test]
+    fn percent_mode() {
+        let config = LexerConfig {
+            percent: true,
+            ..LexerConfig::default()
+        };
+        let eval = |input| {
+            Parser::with_config(input, &config)?
+                .parse()
+                .map(|e| e.evaluate())
+        };
+        assert_eq!(eval("200 + 10%"), Ok(Ok(220)));
+        assert_eq!(eval("200 - 10%"), Ok(Ok(180)));
+        assert_eq!(eval("200 * 10%"), Ok(Ok(20)));
+        assert_eq!(eval("7 mod 2"), Ok(Ok(1)));
+        // Without the flag `%` is still the remainder operator.
+        assert!(matches!(
+            parse("200 + 10%"),
+            Err(ParseError::ExpectedOperand {
+                operator: Token::Modulo,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn number_too_large() {
+        assert_eq!(
+            parse("1 + 99999999999999999999999"),
+            Err(ParseError::NumberTooLarge {
+                literal: "99999999999999999999999".to_string(),
+                pos: 4,
+            })
+        );
+        assert_eq!(parse("9223372036854775807"), Ok(Expression::num(i64::MAX)));
+    }
+
+    #[test]
+    fn missing_closing_paren() {
+        let error = parse("(1 + 2").unwrap_err();
+        assert_eq!(
+            error,
+            ParseError::Expected {
+                expected: Token::RightParen,
+                found: None,
+                pos: 6,
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Expected ')', found end of input at position 6"
+        );
+        assert_eq!(
+            parse("(1 + 2 3").unwrap_err().to_string(),
+            "Expected ')', found '3' at position 7"
+        );
+    }
+
+    #[test]
+    fn unicode_operators() {
+        let eval = |input| parse(input).unwrap().evaluate();
+        assert_eq!(eval("6 × 7"), Ok(42));
+        assert_eq!(eval("84 ÷ 2"), Ok(42));
+        assert_eq!(eval("84 ∕ 2"), Ok(42));
+        assert_eq!(eval("5 − 3"), Ok(2));
+        assert_eq!(eval("−3"), Ok(-3));
+        assert_eq!(parse("6 × 7 − 84 ÷ 2"), parse("6 * 7 - 84 / 2"));
+        assert_eq!(eval("2 × 3 + 8 / 4 − 1"), Ok(7));
+    }
+
+    #[test]
+    fn comparisons_do_not_chain() {
+        assert_eq!(
+            parse("1 + 1 >= 2"),
+            Ok(Expression::compare(
+                CompareOp::GreaterEqual,
+                Expression::add(Expression::num(1), Expression::num(1)),
+                Expression::num(2)
+            ))
+        );
+        assert_eq!(parse("1 < 2").unwrap().evaluate(), Ok(1));
+        assert_eq!(
+            parse("1 < 2 < 3"),
+            Err(ParseError::ChainedComparison { pos: 6 })
+        );
+        assert_eq!(
+            parse("1 == 1 != 0"),
+            Err(ParseError::ChainedComparison { pos: 7 })
+        );
+        // Parentheses make the grouping explicit.
+        assert!(parse("(1 < 2) < 3").is_ok());
+    }
+
+    #[test]
+    fn mod_keyword() {
+        assert_eq!(parse("7 mod 3"), parse("7 % 3"));
+        assert_eq!(parse("7 mod 3").unwrap().evaluate(), Ok(1));
+        assert_eq!(parse("2 + 6 mod 4"), parse("2 + (6 % 4)"));
+        assert_eq!(parse("2 + 6 mod 4").unwrap().evaluate(), Ok(4));
+        // `mod` is reserved, so it can't be a variable.
+        assert_eq!(
+            parse("mod + 1"),
+            Err(ParseError::UnexpectedToken {
+                token: Token::Modulo,
+                pos: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn line_and_column_of_an_error() {
+        let input = "1 +\n2 * )";
+        let error = parse(input).unwrap_err();
+        assert_eq!(error.line_column(input), (2, 5));
+        assert_eq!(
+            error.describe_in(input),
+            "Expected an operand after '*' at line 2, column 5"
+        );
+        // Columns count characters, so a multi-byte operator is one column.
+        let input = "6 ×\n  7 ÷ #";
+        assert_eq!(parse(input).unwrap_err().line_column(input), (2, 7));
+    }
+
+    #[test]
+    fn long_literal_is_cut_off() {
+        let input = "9".repeat(10_000);
+        let Err(ParseError::NumberTooLarge { literal, pos: 0 }) = parse(&input) else {
+            panic!("expected NumberTooLarge");
+        };
+        // The digits past the limit are never copied.
+        assert_eq!(literal, format!("{}…", "9".repeat(64)));
+
+        let config = LexerConfig {
+            max_digits: 3,
+            ..LexerConfig::default()
+        };
+        assert_eq!(
+            Parser::with_config("1 + 1234", &config).map(|_| ()),
+            Err(ParseError::NumberTooLarge {
+                literal: "123…".to_string(),
+                pos: 4,
+            })
+        );
+        assert!(Parser::with_config("1 + 123", &config).is_ok());
+    }
+
+    #[test]
+    fn token_spans() {
+        assert_eq!(
+            lex_with_spans("12 + 345"),
+            Ok(vec![
+                (Token::Number(12), 0..2),
+                (Token::Plus, 3..4),
+                (Token::Number(345), 5..8),
+            ])
+        );
+        // Spans are byte ranges, so `×` takes two bytes.
+        assert_eq!(
+            lex_with_spans("2×x"),
+            Ok(vec![
+                (Token::Number(2), 0..1),
+                (Token::Times, 1..3),
+                (Token::Identifier("x".to_string()), 3..4),
+            ])
+        );
+    }
+
+    #[test]
+    fn square_brackets_and_braces() {
+        assert_eq!(parse("[2*(3+4)]").unwrap().evaluate(), Ok(14));
+        assert_eq!(parse("{1 + [2]}"), parse("1 + 2"));
+        assert_eq!(
+            parse("[2+3)"),
+            Err(ParseError::MismatchedBracket {
+                open: Token::LeftBracket,
+                found: Token::RightParen,
+                pos: 4,
+            })
+        );
+        assert_eq!(
+            parse("{1"),
+            Err(ParseError::Expected {
+                expected: Token::RightBrace,
+                found: None,
+                pos: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn lone_operators() {
+        for (input, token) in [("+", Token::Plus), ("-", Token::Minus), ("*", Token::Times)] {
+            assert_eq!(
+                parse(input),
+                Err(ParseError::UnexpectedToken { token, pos: 0 })
+            );
+        }
+        assert_eq!(
+            parse(")"),
+            Err(ParseError::UnmatchedBracket {
+                found: Token::RightParen,
+                pos: 0,
+            })
+        );
+        assert_eq!(
+            parse(")").unwrap_err().to_string(),
+            "Unmatched closing ')' at position 0"
+        );
+    }
+
+    #[test]
+    fn lone_operators_before_a_separator_or_closing_bracket() {
+        let program = |input| Parser::new(input)?.parse_program();
+        for (input, token, pos) in [
+            ("+;", Token::Plus, 0),
+            ("(-)", Token::Minus, 1),
+            ("[+]", Token::Plus, 1),
+            ("1; -", Token::Minus, 3),
+        ] {
+            assert_eq!(
+                program(input),
+                Err(ParseError::UnexpectedToken { token, pos }),
+                "{input}"
+            );
+        }
+        assert!(matches!(
+            program("1 -;"),
+            Err(ParseError::ExpectedOperand {
+                operator: Token::Minus,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn redundant_parens() {
+        let redundant = |input| redundant_parentheses(input, &LexerConfig::default());
+        assert_eq!(redundant("(2 + 3)"), Ok(vec![0]));
+        assert_eq!(redundant("(2 + 3) * 4"), Ok(vec![]));
+        assert_eq!(redundant("(2) + ((3))"), Ok(vec![0, 6, 7]));
+        assert_eq!(redundant("max(1, 2)"), Ok(vec![]));
+    }
+
+    #[test]
+    fn let_syntax() {
+        assert_eq!(
+            parse("let x = 5 in x * x"),
+            Ok(Expression::let_in(
+                "x",
+                Expression::num(5),
+                Expression::mul(Expression::var("x"), Expression::var("x"))
+            ))
+        );
+        assert_eq!(
+            parse("let 2 = 3 in 4"),
+            Err(ParseError::InvalidAssignmentTarget { pos: 4 })
+        );
+        assert_eq!(
+            parse("let x = 1"),
+            Err(ParseError::Expected {
+                expected: Token::In,
+                found: None,
+                pos: 9,
+            })
+        );
+    }
+
+    #[test]
+    fn absolute_value_bars() {
+        let eval = |input| parse(input).unwrap().evaluate();
+        assert_eq!(
+            parse("|3 - 7|"),
+            Ok(Expression::call("abs", vec![parse("3 - 7").unwrap()]))
+        );
+        assert_eq!(eval("|−5|"), Ok(5));
+        assert_eq!(eval("|3 - 7|"), Ok(4));
+        assert_eq!(eval("||-2| - 5|"), Ok(3));
+        assert_eq!(eval("1 + |-2| * 3"), Ok(7));
+        assert_eq!(
+            parse("|3"),
+            Err(ParseError::Expected {
+                expected: Token::Bar,
+                found: None,
+                pos: 2,
+            })
+        );
+        // Where an operator is expected, a bar can only close one.
+        assert_eq!(
+            parse("2 |3|"),
+            Err(ParseError::UnmatchedBracket {
+                found: Token::Bar,
+                pos: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn negative_exponents() {
+        let decimal = |mantissa, scale| Token::Decimal { mantissa, scale };
+        assert_eq!(lex("5e-1"), Ok(vec![decimal(5, 1)]));
+        assert_eq!(lex("2.5e-2"), Ok(vec![decimal(25, 3)]));
+        assert_eq!(lex("1.5e-1"), Ok(vec![decimal(15, 2)]));
+        // Zeros before the point cancel places, leaving an integer.
+        assert_eq!(lex("50e-1"), Ok(vec![Token::Number(5)]));
+        assert_eq!(lex("1200E-2"), Ok(vec![Token::Number(12)]));
+        assert_eq!(lex("0e-5"), Ok(vec![Token::Number(0)]));
+        assert_eq!(parse("2.5e-2").unwrap().evaluate_rational(), Ok((1, 40)));
+        assert_eq!(parse("5e-1 + .5").unwrap().evaluate_rational(), Ok((1, 1)));
+        assert_eq!(parse("2e-"), Err(ParseError::InvalidNumber { pos: 0 }));
+        assert!(matches!(
+            parse("1e-30"),
+            Err(ParseError::NumberTooLarge { .. })
+        ));
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use crate::expression::{CompareOp, Expression};
+
+impl Expression {
+    /// A conservative `(min, max)` interval for the value, computed with
+    /// interval arithmetic. `None` when some operation could overflow or fail,
+    /// as for a division by an interval containing zero, or for a variable.
+    pub fn value_range(&self) -> Option<(i64, i64)> {
+        self.value_range_with(&HashMap::new())
+    }
+
+    /// Like `value_range`, with each variable in `bounds` ranging over the
+    /// inclusive interval it maps to, so `x * 2 + 1` with `x` in `(0, 10)` is
+    /// in `(1, 21)`.
+    pub fn value_range_with(&self, bounds: &HashMap<String, (i64, i64)>) -> Option<(i64, i64)> {
+        match self {
+            Expression::Number(n) => Some((*n, *n)),
+            Expression::Variable(name) => bounds.get(name).copied().filter(|(lo, hi)| lo <= hi),
+            // A zero factor decides the product, as in `evaluate`.
+            Expression::Multiplication(left, right) => {
+                let left = left.value_range_with(bounds);
+                if left == Some((0, 0)) {
+                    return left;
+                }
+                // `None` on the left may stand for any failure, not only an
+                // overflow, so it has to be ruled out.
+                let (left, right) = (left?, right.value_range_with(bounds)?);
+                if right == (0, 0) {
+                    return Some(right);
+                }
+                self.corners(&[left, right])
+            }
+            // The bound name ranges over the value's interval in the body.
+            Expression::Let { name, value, body } => {
+                let mut inner = bounds.clone();
+                inner.insert(name.clone(), value.value_range_with(bounds)?);
+                body.value_range_with(&inner)
+            }
+            Expression::Conditional { cond, then, els } => match cond.value_range_with(bounds)? {
+                (0, 0) => els.value_range_with(bounds),
+                (lo, hi) if lo > 0 || hi < 0 => then.value_range_with(bounds),
+                _ => {
+                    let (then_lo, then_hi) = then.value_range_with(bounds)?;
+                    let (els_lo, els_hi) = els.value_range_with(bounds)?;
+                    Some((then_lo.min(els_lo), then_hi.max(els_hi)))
+                }
+            },
+            _ => {
+                let ranges = self
+                    .children()
+                    .into_iter()
+                    .map(|child| child.value_range_with(bounds))
+                    .collect::<Option<Vec<_>>>()?;
+                self.combine(&ranges)
+            }
+        }
+    }
+
+    // The range of this operator applied to operands in `ranges`.
+    fn combine(&self, ranges: &[(i64, i64)]) -> Option<(i64, i64)> {
+        // Point intervals are simply evaluated.
+        if ranges.iter().all(|(lo, hi)| lo == hi) {
+            let value = self.eval_at(&ranges.iter().map(|&(n, _)| n).collect::<Vec<_>>())?;
+            return Some((value, value));
+        }
+        match (self, ranges) {
+            (Expression::Division(..) | Expression::Remainder(..), [_, (lo, hi)])
+                if *lo <= 0 && *hi >= 0 =>
+            {
+                None
+            }
+            // `i64::MIN % -1` overflows.
+            (Expression::Remainder(..), [(i64::MIN, _), (lo, hi)]) if *lo <= -1 && *hi >= -1 => {
+                None
+            }
+            // The remainder takes the sign of the dividend and is smaller in
+            // magnitude than the divisor.
+            (Expression::Remainder(..), [(lo, hi), divisor]) => {
+                let limit =
+                    i64::try_from(divisor.0.unsigned_abs().max(divisor.1.unsigned_abs()) - 1)
+                        .unwrap_or(i64::MAX);
+                Some((
+                    if *lo >= 0 { 0 } else { (*lo).max(-limit) },
+                    if *hi <= 0 { 0 } else { (*hi).min(limit) },
+                ))
+            }
+            (Expression::Power(..), [base, exponent]) => power_range(*base, *exponent),
+            (Expression::Call { name, .. }, [base, exponent]) if name == "pow" => {
+                power_range(*base, *exponent)
+            }
+            (Expression::Call { name, .. }, [(lo, hi)]) if name == "abs" && *lo < 0 && *hi > 0 => {
+                Some((0, lo.checked_neg()?.max(*hi)))
+            }
+            (Expression::Call { name, .. }, [a, b]) if name == "gcd" => {
+                let magnitude = [a.0, a.1, b.0, b.1]
+                    .into_iter()
+                    .map(i64::checked_abs)
+                    .try_fold(0, |max, n| Some(max.max(n?)))?;
+                Some((0, magnitude))
+            }
+            (Expression::Comparison { op, .. }, [(l_lo, l_hi), (r_lo, r_hi)])
+                if matches!(op, CompareOp::Equal | CompareOp::NotEqual) =>
+            {
+                let holds = if l_hi < r_lo || r_hi < l_lo {
+                    // Disjoint intervals can never be equal.
+                    Some(*op == CompareOp::NotEqual)
+                } else {
+                    None
+                };
+                Some(holds.map_or((0, 1), |holds| (holds as i64, holds as i64)))
+            }
+            // Everything else is monotonic in each operand, so its extremes
+            // lie at the corners: `+`, `-`, `*`, `/` (with a divisor of one
+            // sign), negation, factorial, the ordering comparisons, `min`,
+            // `max`, `abs` of one sign, `sqrt` and the logarithms.
+            _ => self.corners(ranges),
+        }
+    }
+
+    // The smallest and largest value at each combination of interval ends.
+    // Any failing corner fails the whole range.
+    fn corners(&self, ranges: &[(i64, i64)]) -> Option<(i64, i64)> {
+        let mut min = i64::MAX;
+        let mut max = i64::MIN;
+        for mask in 0..1u32 << ranges.len() {
+            let values: Vec<i64> = ranges
+                .iter()
+                .enumerate()
+                .map(|(i, &(lo, hi))| if mask & (1 << i) == 0 { lo } else { hi })
+                .collect();
+            let value = self.eval_at(&values)?;
+            min = min.min(value);
+            max = max.max(value);
+        }
+        Some((min, max))
+    }
+
+    // This node evaluated with its children replaced by `values`.
+    fn eval_at(&self, values: &[i64]) -> Option<i64> {
+        let mut values = values.iter();
+        self.map_children(|_| Expression::Number(*values.next().expect("one value per child")))
+            .evaluate()
+            .ok()
+    }
+}
+
+// Only non-negative exponents are valid. A non-negative base makes the
+// result grow with both operands apart from `0 ** 0`, which the corners
+// include; a base that can be negative is bounded by its magnitude.
+fn power_range(base: (i64, i64), exponent: (i64, i64)) -> Option<(i64, i64)> {
+    if exponent.0 < 0 {
+        return None;
+    }
+    let exponent_max = u32::try_from(exponent.1).ok()?;
+    if base.0 >= 0 {
+        let corners = [
+            base.0.checked_pow(exponent.0 as u32)?,
+            base.0.checked_pow(exponent_max)?,
+            base.1.checked_pow(exponent.0 as u32)?,
+            base.1.checked_pow(exponent_max)?,
+        ];
+        return Some((*corners.iter().min()?, *corners.iter().max()?));
+    }
+    let magnitude = base.0.checked_neg()?.max(base.1);
+    let bound = magnitude.checked_pow(exponent_max)?.max(1);
+    Some((-bound, bound))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input)
+            .and_then(|mut parser| parser.parse())
+            .expect("test input parses")
+    }
+
+    #[test]
+    fn zero_factor_on_the_left() {
+        assert_eq!(
+            parse("0 * (9223372036854775807 * 2)").value_range(),
+            Some((0, 0))
+        );
+        assert_eq!(
+            parse("-(56 + 0 * (88! * 35))").value_range(),
+            Some((-56, -56))
+        );
+        // A left operand with no known range keeps the product unknown,
+        // even next to a zero.
+        assert_eq!(parse("(1 / 0) * 0").value_range(), None);
+    }
+
+    #[test]
+    fn interval_arithmetic() {
+        assert_eq!(parse("2 * 3 + 1").value_range(), Some((7, 7)));
+        let bounds = HashMap::from([("x".to_string(), (-3, 4))]);
+        assert_eq!(parse("x * 2 + 1").value_range_with(&bounds), Some((-5, 9)));
+        assert_eq!(parse("x * x").value_range_with(&bounds), Some((-12, 16)));
+        // The divisor's interval contains zero.
+        assert_eq!(parse("1 / (x - 1)").value_range_with(&bounds), None);
+        assert_eq!(
+            parse("9223372036854775807 + x").value_range_with(&bounds),
+            None
+        );
+        assert_eq!(parse("x + 1").value_range(), None);
+    }
+}
@@ -0,0 +1,127 @@
+use std::cmp::Ordering;
+
+use crate::eval::{Arithmetic, Environment, EvalError};
+use crate::expression::Expression;
+
+// Always reduced with a positive denominator, so the derived equality is
+// value equality.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rational {
+    num: i64,
+    den: i64,
+}
+
+impl Rational {
+    // Intermediate products are formed in i128 and only the reduced result
+    // has to fit back into i64.
+    fn new(num: i128, den: i128) -> Option<Self> {
+        if den == 0 {
+            return None;
+        }
+        let g = gcd(num, den).max(1);
+        let sign = if den < 0 { -1 } else { 1 };
+        Some(Rational {
+            num: i64::try_from(sign * num / g).ok()?,
+            den: i64::try_from(sign * den / g).ok()?,
+        })
+    }
+
+    fn parts(self) -> (i128, i128) {
+        (self.num as i128, self.den as i128)
+    }
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b) = self.parts();
+        let (c, d) = other.parts();
+        (a * d).cmp(&(c * b))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Arithmetic for Rational {
+    fn from_i64(n: i64) -> Self {
+        Rational { num: n, den: 1 }
+    }
+
+    fn try_add(&self, rhs: &Self) -> Option<Self> {
+        let ((a, b), (c, d)) = (self.parts(), rhs.parts());
+        Rational::new(a.checked_mul(d)?.checked_add(c.checked_mul(b)?)?, b * d)
+    }
+
+    fn try_sub(&self, rhs: &Self) -> Option<Self> {
+        self.try_add(&rhs.try_neg()?)
+    }
+
+    fn try_mul(&self, rhs: &Self) -> Option<Self> {
+        let ((a, b), (c, d)) = (self.parts(), rhs.parts());
+        Rational::new(a * c, b * d)
+    }
+
+    fn try_div(&self, rhs: &Self) -> Option<Self> {
+        let ((a, b), (c, d)) = (self.parts(), rhs.parts());
+        Rational::new(a * d, b * c)
+    }
+
+    // Truncated like integer `%`: `self - rhs * trunc(self / rhs)`.
+    fn try_rem(&self, rhs: &Self) -> Option<Self> {
+        let ((a, b), (c, d)) = (self.parts(), rhs.parts());
+        let quotient = (a * d) / (b * c);
+        let (q, r) = (quotient.checked_mul(c)?, d);
+        Rational::new(a.checked_mul(r)?.checked_sub(q.checked_mul(b)?)?, b * r)
+    }
+
+    fn try_neg(&self) -> Option<Self> {
+        Some(Rational {
+            num: self.num.checked_neg()?,
+            den: self.den,
+        })
+    }
+
+    fn is_integer(&self) -> bool {
+        self.den == 1
+    }
+}
+
+impl Expression {
+    /// Evaluates with exact fractions, returning the reduced
+    /// `(numerator, denominator)` with a positive denominator.
+    pub fn evaluate_rational(&self) -> Result<(i64, i64), EvalError> {
+        let value = self.evaluate_as::<Rational>(&Environment::new())?;
+        Ok((value.num, value.den))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn rational(input: &str) -> Result<(i64, i64), EvalError> {
+        Parser::new(input)
+            .and_then(|mut parser| parser.parse())
+            .expect("test input parses")
+            .evaluate_rational()
+    }
+
+    #[test]
+    fn exact_fractions() {
+        assert_eq!(rational("1/3 + 1/6"), Ok((1, 2)));
+        assert_eq!(rational("2/4"), Ok((1, 2)));
+        assert_eq!(rational("1/-2"), Ok((-1, 2)));
+        assert_eq!(rational("6/3"), Ok((2, 1)));
+    }
+}
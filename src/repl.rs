@@ -0,0 +1,1009 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::eval::{Environment, EvalError, IntegerWidth, OverflowMode};
+use crate::expression::{Expression, TreeStyle};
+use crate::format::{FormatOptions, SUPPORTED_RADIXES, render_value};
+use crate::json::write_json_string;
+use crate::parser::{LexerConfig, ParseError, Parser, redundant_parentheses};
+use crate::statement::{Statement, run_program, run_program_width};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplError {
+    UnknownCommand(String),
+    BadArguments { command: String, reason: String },
+    Parse(ParseError),
+    Eval(EvalError),
+}
+
+impl fmt::Display for ReplError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplError::UnknownCommand(name) => write!(f, "Unknown command: ':{}'", name),
+            ReplError::BadArguments { command, reason } => {
+                write!(f, "Bad arguments for ':{}': {}", command, reason)
+            }
+            ReplError::Parse(e) => write!(f, "{}", e),
+            ReplError::Eval(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplError {}
+
+impl From<ParseError> for ReplError {
+    fn from(e: ParseError) -> Self {
+        ReplError::Parse(e)
+    }
+}
+
+impl From<EvalError> for ReplError {
+    fn from(e: EvalError) -> Self {
+        ReplError::Eval(e)
+    }
+}
+
+/// Why `--file` produced no value: the file could not be read, or the
+/// program in it failed.
+#[derive(Debug)]
+pub enum FileError {
+    Read { path: PathBuf, error: io::Error },
+    Program { source: String, error: ReplError },
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileError::Read { path, error } => {
+                write!(f, "cannot read '{}': {}", path.display(), error)
+            }
+            // Syntax errors are located by line and column in the file.
+            FileError::Program {
+                source,
+                error: ReplError::Parse(e),
+            } => write!(f, "{}", e.describe_in(source)),
+            FileError::Program { error, .. } => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Quit,
+    Help,
+    Vars,
+    Base(u32),
+    Mode(OverflowMode),
+    Width(IntegerWidth),
+    Sexpr(Expression),
+    Latex(Expression),
+    Parens(Expression),
+    Steps(Expression),
+    JsonTree(Expression),
+    /// Depth limit for printed trees; `None` prints them whole.
+    Tree(Option<usize>),
+    ShowTree(bool),
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, ReplError> {
+        Self::parse_with(line, &LexerConfig::default())
+    }
+
+    /// Like `parse`, lexing expression arguments with `config`.
+    pub fn parse_with(line: &str, config: &LexerConfig) -> Result<Command, ReplError> {
+        let line = line.strip_prefix(':').unwrap_or(line).trim_start();
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+        let rest = line[name.len()..].trim();
+
+        match name {
+            "quit" | "q" => {
+                no_arguments(name, &args)?;
+                Ok(Command::Quit)
+            }
+            "help" | "commands" => {
+                no_arguments(name, &args)?;
+                Ok(Command::Help)
+            }
+            "vars" => {
+                no_arguments(name, &args)?;
+                Ok(Command::Vars)
+            }
+            "base" => {
+                let radix = match args.as_slice() {
+                    [radix] => radix.parse::<u32>().ok(),
+                    _ => None,
+                };
+                match radix {
+                    Some(radix) if SUPPORTED_RADIXES.contains(&radix) => Ok(Command::Base(radix)),
+                    _ => Err(ReplError::BadArguments {
+                        command: name.to_string(),
+                        reason: "expected one of 2, 8, 10 or 16".to_string(),
+                    }),
+                }
+            }
+            "mode" => match args.as_slice() {
+                ["checked"] => Ok(Command::Mode(OverflowMode::Checked)),
+                ["wrapping"] => Ok(Command::Mode(OverflowMode::Wrapping)),
+                _ => Err(ReplError::BadArguments {
+                    command: name.to_string(),
+                    reason: "expected 'checked' or 'wrapping'".to_string(),
+                }),
+            },
+            "tree" => match args.as_slice() {
+                ["on"] => Ok(Command::ShowTree(true)),
+                ["off"] => Ok(Command::ShowTree(false)),
+                ["all"] => Ok(Command::Tree(None)),
+                [depth] if depth.parse::<usize>().is_ok() => Ok(Command::Tree(depth.parse().ok())),
+                _ => Err(ReplError::BadArguments {
+                    command: name.to_string(),
+                    reason: "expected 'on', 'off', 'all' or a depth".to_string(),
+                }),
+            },
+            "width" => match args.as_slice() {
+                ["32"] => Ok(Command::Width(IntegerWidth::I32)),
+                ["64"] => Ok(Command::Width(IntegerWidth::I64)),
+                ["128"] => Ok(Command::Width(IntegerWidth::I128)),
+                _ => Err(ReplError::BadArguments {
+                    command: name.to_string(),
+                    reason: "expected 32, 64 or 128".to_string(),
+                }),
+            },
+            "sexpr" => Ok(Command::Sexpr(expression_argument(name, rest, config)?)),
+            "latex" => Ok(Command::Latex(expression_argument(name, rest, config)?)),
+            "parens" => Ok(Command::Parens(expression_argument(name, rest, config)?)),
+            "json" => Ok(Command::JsonTree(expression_argument(name, rest, config)?)),
+            "steps" => Ok(Command::Steps(expression_argument(name, rest, config)?)),
+            _ => Err(ReplError::UnknownCommand(name.to_string())),
+        }
+    }
+}
+
+const HELP: &str = "\
+Comandos:
+  :help                     mostra esta ajuda (também :commands)
+  :base <2|8|10|16>         escolhe a base dos resultados
+  :mode <checked|wrapping>  acusa overflow ou dá a volta como o hardware
+  :width <32|64|128>        escolhe a largura dos inteiros
+  :tree <on|off|all|n>      mostra ou oculta a árvore, ou limita sua profundidade
+  :vars                     lista as variáveis definidas
+  :sexpr <expressão>        mostra a expressão como S-expression
+  :latex <expressão>        mostra a expressão em LaTeX
+  :parens <expressão>       mostra a expressão com todos os parênteses
+  :json <expressão>         mostra a árvore como JSON indentado
+  :steps <expressão>        mostra a avaliação passo a passo
+  :quit                     encerra (o mesmo que 'sair' ou 'exit')";
+
+/// Everything the interactive loop keeps from one line to the next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplState {
+    /// Base, digit grouping and sign of printed results.
+    pub format: FormatOptions,
+    pub mode: OverflowMode,
+    pub width: IntegerWidth,
+    pub tree_style: TreeStyle,
+    /// Whether each result is preceded by its syntax tree.
+    pub show_tree: bool,
+    /// Whether each result is preceded by the value of every node.
+    pub trace: bool,
+    /// Points out parentheses that do not change how the input parses.
+    pub lint_parens: bool,
+    pub env: Environment,
+    pub lexer: LexerConfig,
+}
+
+impl ReplState {
+    pub fn format_value(&self, value: i128) -> String {
+        render_value(value, &self.format)
+    }
+}
+
+impl Default for ReplState {
+    fn default() -> Self {
+        ReplState {
+            format: FormatOptions::default(),
+            mode: OverflowMode::default(),
+            width: IntegerWidth::default(),
+            tree_style: TreeStyle::default(),
+            show_tree: true,
+            trace: false,
+            lint_parens: false,
+            env: Environment::new(),
+            lexer: LexerConfig::default(),
+        }
+    }
+}
+
+/// How `run_interactive` presents itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplConfig {
+    /// Written, without a newline, before each line is read.
+    pub prompt: String,
+    /// Leaves out the banner and the prompt.
+    pub quiet: bool,
+    /// Answers each line with `json_response` instead of the full report,
+    /// which also implies `quiet`.
+    pub json: bool,
+}
+
+impl Default for ReplConfig {
+    fn default() -> Self {
+        ReplConfig {
+            prompt: "Expressão: ".to_string(),
+            quiet: false,
+            json: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandResult {
+    /// The state changed; the message confirms the new setting.
+    Updated(String),
+    /// Text produced by the command itself, such as a rendering or a listing.
+    Output(String),
+    Quit,
+    Failed(ReplError),
+}
+
+/// Parses a `:` command and applies it to `state`.
+pub fn handle_command(line: &str, state: &mut ReplState) -> CommandResult {
+    let command = match Command::parse_with(line, &state.lexer) {
+        Ok(command) => command,
+        Err(e) => return CommandResult::Failed(e),
+    };
+    match command {
+        Command::Quit => CommandResult::Quit,
+        Command::Help => CommandResult::Output(HELP.to_string()),
+        Command::Vars => {
+            let mut names: Vec<_> = state.env.keys().collect();
+            names.sort();
+            let lines: Vec<String> = names
+                .into_iter()
+                .map(|name| format!("{} = {}", name, state.format_value(state.env[name])))
+                .collect();
+            if lines.is_empty() {
+                CommandResult::Output("Nenhuma variável definida".to_string())
+            } else {
+                CommandResult::Output(lines.join("\n"))
+            }
+        }
+        Command::Base(radix) => {
+            state.format.radix = radix;
+            CommandResult::Updated(format!("Base de saída: {}", radix))
+        }
+        Command::Mode(mode) => {
+            state.mode = mode;
+            CommandResult::Updated(match mode {
+                OverflowMode::Checked => "Modo de overflow: checked".to_string(),
+                OverflowMode::Wrapping => "Modo de overflow: wrapping".to_string(),
+            })
+        }
+        Command::Width(width) => {
+            state.width = width;
+            CommandResult::Updated(match width {
+                IntegerWidth::I32 => "Largura dos inteiros: 32 bits".to_string(),
+                IntegerWidth::I64 => "Largura dos inteiros: 64 bits".to_string(),
+                IntegerWidth::I128 => "Largura dos inteiros: 128 bits".to_string(),
+            })
+        }
+        Command::Tree(max_depth) => {
+            state.tree_style.max_depth = max_depth;
+            CommandResult::Updated(match max_depth {
+                Some(depth) => format!("Profundidade da árvore: {}", depth),
+                None => "Profundidade da árvore: completa".to_string(),
+            })
+        }
+        Command::ShowTree(show) => {
+            state.show_tree = show;
+            CommandResult::Updated(if show {
+                "Árvore sintática: visível".to_string()
+            } else {
+                "Árvore sintática: oculta".to_string()
+            })
+        }
+        Command::Sexpr(expr) => CommandResult::Output(expr.to_sexpr()),
+        Command::Latex(expr) => CommandResult::Output(expr.to_latex()),
+        Command::Parens(expr) => CommandResult::Output(expr.to_string_full_parens()),
+        Command::JsonTree(expr) => CommandResult::Output(expr.to_json_tree()),
+        Command::Steps(expr) => {
+            let mut previous = expr.to_string();
+            let mut lines = vec![previous.clone()];
+            let mut current = expr;
+            loop {
+                match current.reduce_step() {
+                    Ok(Some(next)) => {
+                        // Some steps, such as `-(5)` becoming the literal -5,
+                        // do not change the printed form.
+                        let rendered = next.to_string();
+                        if rendered != previous {
+                            lines.push(format!("→ {}", rendered));
+                            previous = rendered;
+                        }
+                        current = next;
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        lines.push(format!("Erro ao avaliar: {}", e));
+                        break;
+                    }
+                }
+            }
+            CommandResult::Output(lines.join("\n"))
+        }
+    }
+}
+
+fn expression_argument(
+    command: &str,
+    rest: &str,
+    config: &LexerConfig,
+) -> Result<Expression, ReplError> {
+    if rest.is_empty() {
+        return Err(ReplError::BadArguments {
+            command: command.to_string(),
+            reason: "expected an expression".to_string(),
+        });
+    }
+    parse_input(rest, config)
+}
+
+fn no_arguments(command: &str, args: &[&str]) -> Result<(), ReplError> {
+    if args.is_empty() {
+        Ok(())
+    } else {
+        Err(ReplError::BadArguments {
+            command: command.to_string(),
+            reason: "this command takes no arguments".to_string(),
+        })
+    }
+}
+
+pub fn is_command(line: &str) -> bool {
+    line.starts_with(':')
+}
+
+pub fn parse_input(line: &str, config: &LexerConfig) -> Result<Expression, ReplError> {
+    let expr = Parser::with_config(line, config)?.parse()?;
+    Ok(expr)
+}
+
+pub fn parse_program_input(line: &str, config: &LexerConfig) -> Result<Vec<Statement>, ReplError> {
+    let program = Parser::with_config(line, config)?.parse_program()?;
+    Ok(program)
+}
+
+/// Parses and runs `line` as a program and describes the outcome as one JSON
+/// object: `{"input":...,"result":...,"error":...,"tree":...}`. `tree` is the
+/// last statement's expression, and `result` is `null` whenever `error` is set.
+pub fn json_response(line: &str, state: &mut ReplState) -> String {
+    let program = parse_program_input(line, &state.lexer);
+    let tree = match &program {
+        Ok(program) => program.last().map(|s| s.expression().to_json()),
+        Err(_) => None,
+    };
+    let outcome = program.and_then(|program| {
+        run_program_width(&program, &mut state.env, state.mode, state.width)
+            .map_err(ReplError::from)
+    });
+
+    let mut out = String::from("{\"input\":");
+    write_json_string(&mut out, line);
+    match outcome {
+        Ok(Some(value)) => out.push_str(&format!(",\"result\":{},\"error\":null", value)),
+        Ok(None) => out.push_str(",\"result\":null,\"error\":null"),
+        Err(e) => {
+            out.push_str(",\"result\":null,\"error\":");
+            write_json_string(&mut out, &e.to_string());
+        }
+    }
+    out.push_str(",\"tree\":");
+    out.push_str(tree.as_deref().unwrap_or("null"));
+    out.push('}');
+    out
+}
+
+/// Describes what a `:` command did as one JSON object:
+/// `{"input":...,"output":...,"error":...}`. `output` is the confirmation or
+/// text the command produced, and is `null` whenever `error` is set.
+pub fn json_command_response(line: &str, result: &CommandResult) -> String {
+    let mut out = String::from("{\"input\":");
+    write_json_string(&mut out, line);
+    match result {
+        CommandResult::Updated(text) | CommandResult::Output(text) => {
+            out.push_str(",\"output\":");
+            write_json_string(&mut out, text);
+            out.push_str(",\"error\":null");
+        }
+        CommandResult::Quit => out.push_str(",\"output\":null,\"error\":null"),
+        CommandResult::Failed(e) => {
+            out.push_str(",\"output\":null,\"error\":");
+            write_json_string(&mut out, &e.to_string());
+        }
+    }
+    out.push('}');
+    out
+}
+
+/// Parses and runs `source` as one program in a fresh environment with
+/// checked arithmetic. Newlines are whitespace, so it may span several lines.
+pub fn evaluate_source(source: &str, config: &LexerConfig) -> Result<Option<i64>, ReplError> {
+    let program = parse_program_input(source, config)?;
+    Ok(run_program(
+        &program,
+        &mut Environment::new(),
+        OverflowMode::Checked,
+    )?)
+}
+
+/// Reads the whole file at `path` and runs it as one program, as
+/// [`evaluate_source`] does.
+pub fn evaluate_file(path: &Path, config: &LexerConfig) -> Result<Option<i64>, FileError> {
+    let source = std::fs::read_to_string(path).map_err(|error| FileError::Read {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    evaluate_source(&source, config).map_err(|error| FileError::Program { source, error })
+}
+
+/// The parsed tree of `source` in Rust's pretty `Debug` form, as printed by
+/// `--ast-only`.
+pub fn ast_only(source: &str, config: &LexerConfig) -> Result<String, ReplError> {
+    Ok(format!("{:#?}", parse_input(source, config)?))
+}
+
+/// The interactive session: reads lines from `input` until `sair`, `exit`,
+/// `:quit` or the end of the input, keeping `state` from line to line.
+pub fn run_interactive<R: BufRead, W: Write>(
+    mut input: R,
+    out: &mut W,
+    state: &mut ReplState,
+    config: &ReplConfig,
+) -> io::Result<()> {
+    let quiet = config.quiet || config.json;
+    if !quiet {
+        writeln!(out, "=== Calculadora de Expressões ===")?;
+        writeln!(
+            out,
+            "Digite uma expressão matemática (ou 'sair' para encerrar)"
+        )?;
+        writeln!(out, "Exemplos: 10 + 20, (10 + 20) * 30\n")?;
+    }
+
+    loop {
+        if !quiet {
+            write!(out, "{}", config.prompt)?;
+            out.flush()?;
+        }
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            // End of input (Ctrl-D) ends the session like `sair`.
+            if !quiet {
+                writeln!(out)?;
+            }
+            return Ok(());
+        }
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if line == "sair" || line == "exit" {
+            return Ok(());
+        }
+
+        if is_command(line) {
+            match handle_command(line, state) {
+                CommandResult::Quit => return Ok(()),
+                result if config.json => writeln!(out, "{}", json_command_response(line, &result))?,
+                CommandResult::Updated(message) => writeln!(out, "{}\n", message)?,
+                CommandResult::Output(text) => writeln!(out, "{}\n", text)?,
+                CommandResult::Failed(e) => write_error(out, &e)?,
+            }
+            continue;
+        }
+
+        if config.json {
+            writeln!(out, "{}", json_response(line, state))?;
+            continue;
+        }
+
+        match parse_program_input(line, &state.lexer) {
+            Ok(program) if program.is_empty() => {}
+            Ok(program) => {
+                writeln!(out, "\nExpressão simplificada:")?;
+                let rendered: Vec<String> = program.iter().map(|s| s.to_string()).collect();
+                writeln!(out, "{}\n", rendered.join("; "))?;
+
+                if state.lint_parens {
+                    let redundant = redundant_parentheses(line, &state.lexer).unwrap_or_default();
+                    for pos in &redundant {
+                        let column = line[..*pos].chars().count() + 1;
+                        writeln!(out, "Parênteses redundantes na coluna {}", column)?;
+                    }
+                    if !redundant.is_empty() {
+                        writeln!(out)?;
+                    }
+                }
+
+                if state.show_tree {
+                    writeln!(out, "Árvore sintática:")?;
+                    for statement in &program {
+                        statement
+                            .expression()
+                            .write_tree_styled(out, state.tree_style)?;
+                        writeln!(out)?;
+                    }
+                }
+
+                if state.trace {
+                    // Traced on a copy of the environment, with checked 64-bit
+                    // arithmetic, before the program runs for real.
+                    writeln!(out, "Rastreamento:")?;
+                    let mut env = state.env.clone();
+                    let mut trace = String::new();
+                    for statement in &program {
+                        if statement
+                            .expression()
+                            .write_trace(&mut trace, &env, 0)
+                            .is_err()
+                            || statement.execute(&mut env, OverflowMode::Checked).is_err()
+                        {
+                            break;
+                        }
+                    }
+                    writeln!(out, "{}", trace)?;
+                }
+
+                let env = state.env.clone();
+                match run_program_width(&program, &mut state.env, state.mode, state.width) {
+                    Ok(Some(result)) => {
+                        writeln!(out, "Resultado: {}\n", state.format_value(result))?
+                    }
+                    Ok(None) => writeln!(out, "Resultado: none\n")?,
+                    Err(e) => {
+                        writeln!(out, "Erro ao avaliar: {}", e)?;
+                        if let Some(expr) = failing_subexpression(&program, env, state) {
+                            writeln!(out, "Subexpressão que falhou: `{}`", expr)?;
+                        }
+                        writeln!(out)?;
+                    }
+                }
+            }
+            Err(e) => write_error(out, &e)?,
+        }
+    }
+}
+
+// Replays `program` on `env`, the environment from before it ran, for the
+// subtree that failed, in the session's mode and width like the real run.
+fn failing_subexpression(
+    program: &[Statement],
+    mut env: Environment,
+    state: &ReplState,
+) -> Option<Expression> {
+    for statement in program {
+        if let Err(failure) =
+            statement
+                .expression()
+                .evaluate_located_width_with(&env, state.mode, state.width)
+        {
+            return Some(failure.expr);
+        }
+        statement
+            .execute_width(&mut env, state.mode, state.width)
+            .ok()?;
+    }
+    None
+}
+
+fn write_error<W: Write>(out: &mut W, error: &ReplError) -> io::Result<()> {
+    match error {
+        ReplError::UnknownCommand(name) => writeln!(out, "Comando desconhecido: :{}\n", name),
+        ReplError::BadArguments { command, reason } => {
+            writeln!(out, "Argumentos inválidos para :{}: {}\n", command, reason)
+        }
+        ReplError::Parse(e) => writeln!(out, "Erro ao fazer parse: {}\n", e),
+        ReplError::Eval(e) => writeln!(out, "Erro ao avaliar: {}\n", e),
+    }
+}
+
+/// Evaluates every non-empty line of `input` on its own, writing each value
+/// to `out` and each failure as `error: ...` to `err` without stopping.
+pub fn run_batch<R: BufRead, W: Write, E: Write>(
+    input: R,
+    config: &LexerConfig,
+    out: &mut W,
+    err: &mut E,
+) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match evaluate_source(line, config) {
+            Ok(Some(value)) => writeln!(out, "{}", value)?,
+            Ok(None) => {}
+            Err(e) => writeln!(err, "error: {}", e)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs a quiet session over `input` and returns everything it printed.
+    fn session(input: &str, state: &mut ReplState) -> String {
+        let config = ReplConfig {
+            quiet: true,
+            ..ReplConfig::default()
+        };
+        let mut out = Vec::new();
+        run_interactive(input.as_bytes(), &mut out, state, &config).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn unknown_command() {
+        assert_eq!(
+            Command::parse(":frobnicate"),
+            Err(ReplError::UnknownCommand("frobnicate".to_string()))
+        );
+        let out = session(":frobnicate\n1 + 1\n", &mut ReplState::default());
+        assert!(out.starts_with("Comando desconhecido: :frobnicate\n"));
+        assert!(out.contains("Resultado: 2"));
+    }
+
+    fn output(line: &str) -> CommandResult {
+        handle_command(line, &mut ReplState::default())
+    }
+
+    #[test]
+    fn sexpr_command() {
+        assert_eq!(
+            output(":sexpr 2 * 3 + 4"),
+            CommandResult::Output("(+ (* 2 3) 4)".to_string())
+        );
+        assert_eq!(
+            output(":sexpr -(1 + 2)"),
+            CommandResult::Output("(- (+ 1 2))".to_string())
+        );
+    }
+
+    #[test]
+    fn latex_command() {
+        assert_eq!(
+            output(":latex (1+2)/(3+4)"),
+            CommandResult::Output("\\frac{1+2}{3+4}".to_string())
+        );
+    }
+
+    #[test]
+    fn batch_evaluates_each_line() {
+        let input = "1 + 2\n\n  2 * 3  \n1 / 0\nx = 4; x * x\n";
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        run_batch(
+            input.as_bytes(),
+            &LexerConfig::default(),
+            &mut out,
+            &mut err,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "3\n6\n16\n");
+        assert_eq!(
+            String::from_utf8(err).unwrap(),
+            "error: Division by zero in '/'\n"
+        );
+    }
+
+    #[test]
+    fn width_command() {
+        let mut state = ReplState::default();
+        assert_eq!(
+            handle_command(":width 128", &mut state),
+            CommandResult::Updated("Largura dos inteiros: 128 bits".to_string())
+        );
+        assert_eq!(state.width, IntegerWidth::I128);
+        assert_eq!(
+            Command::parse(":width 16"),
+            Err(ReplError::BadArguments {
+                command: "width".to_string(),
+                reason: "expected 32, 64 or 128".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn wide_results_are_printed_and_stored_whole() {
+        let mut state = ReplState::default();
+        handle_command(":width 128", &mut state);
+        let out = session(
+            "9223372036854775807 + 1\na = 9223372036854775807 * 4\n:vars\n",
+            &mut state,
+        );
+        assert!(out.contains("Resultado: 9223372036854775808\n"));
+        assert!(out.contains("Resultado: 36893488147419103228\n"));
+        assert!(out.contains("a = 36893488147419103228"));
+        assert_eq!(
+            json_response("a + 1", &mut state),
+            r#"{"input":"a + 1","result":36893488147419103229,"error":null,"tree":{"Addition":[{"Variable":"a"},{"Number":1}]}}"#
+        );
+
+        handle_command(":mode wrapping", &mut state);
+        assert!(
+            session("9223372036854775807 + 1\n", &mut state)
+                .contains("Resultado: 9223372036854775808\n")
+        );
+
+        // Back at 64 bits the variable no longer fits.
+        handle_command(":width 64", &mut state);
+        handle_command(":mode checked", &mut state);
+        assert!(session("a\n", &mut state).contains("Erro ao avaliar: Overflow in 'variable'"));
+    }
+
+    #[test]
+    fn dispatches_commands() {
+        let mut state = ReplState::default();
+        assert_eq!(handle_command(":quit", &mut state), CommandResult::Quit);
+        assert_eq!(
+            handle_command(":help", &mut state),
+            CommandResult::Output(HELP.to_string())
+        );
+        assert_eq!(
+            handle_command(":tree on", &mut state),
+            CommandResult::Updated("Árvore sintática: visível".to_string())
+        );
+        assert!(state.show_tree);
+        assert_eq!(
+            handle_command(":base 16", &mut state),
+            CommandResult::Updated("Base de saída: 16".to_string())
+        );
+        assert_eq!(state.format.radix, 16);
+        assert_eq!(
+            handle_command(":base 7", &mut state),
+            CommandResult::Failed(ReplError::BadArguments {
+                command: "base".to_string(),
+                reason: "expected one of 2, 8, 10 or 16".to_string(),
+            })
+        );
+        assert_eq!(state.format.radix, 16);
+        assert!(matches!(
+            handle_command(":quit now", &mut state),
+            CommandResult::Failed(ReplError::BadArguments { .. })
+        ));
+    }
+
+    #[test]
+    fn vars_command() {
+        let mut state = ReplState::default();
+        assert_eq!(
+            handle_command(":vars", &mut state),
+            CommandResult::Output("Nenhuma variável definida".to_string())
+        );
+        state.env.insert("y".to_string(), 2);
+        state.env.insert("x".to_string(), 1);
+        assert_eq!(
+            handle_command(":vars", &mut state),
+            CommandResult::Output("x = 1\ny = 2".to_string())
+        );
+    }
+
+    #[test]
+    fn steps_command() {
+        assert_eq!(
+            output(":steps 2 * 3 + 4"),
+            CommandResult::Output("2 * 3 + 4\n→ 6 + 4\n→ 10".to_string())
+        );
+        assert_eq!(
+            output(":steps 1 + 4 / 0"),
+            CommandResult::Output(format!(
+                "1 + 4 / 0\nErro ao avaliar: {}",
+                EvalError::DivisionByZero("/")
+            ))
+        );
+    }
+
+    #[test]
+    fn evaluates_a_file() {
+        let dir = std::env::temp_dir();
+        let file = |name: &str, source: &str| {
+            let path = dir.join(format!("arvore-sintatica-{}-{}", std::process::id(), name));
+            std::fs::write(&path, source).unwrap();
+            let result = evaluate_file(&path, &LexerConfig::default());
+            std::fs::remove_file(&path).unwrap();
+            result
+        };
+        assert_eq!(file("ok", "(1 +\n  2) *\n3\n").unwrap(), Some(9));
+        assert_eq!(file("empty", "").unwrap(), None);
+        assert_eq!(
+            file("syntax", "1 +\n  * 2\n").unwrap_err().to_string(),
+            "Expected an operand after '+' at line 2, column 3"
+        );
+        assert_eq!(
+            file("eval", "1 / 0\n").unwrap_err().to_string(),
+            "Division by zero in '/'"
+        );
+
+        let missing = dir.join("arvore-sintatica-missing");
+        let err = evaluate_file(&missing, &LexerConfig::default()).unwrap_err();
+        assert!(matches!(err, FileError::Read { ref path, .. } if *path == missing));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "cannot read '{}': No such file or directory (os error 2)",
+                missing.display()
+            )
+        );
+    }
+
+    #[test]
+    fn json_command() {
+        assert_eq!(
+            output(":json 1 + 2"),
+            CommandResult::Output(
+                "{\n  \"op\": \"+\",\n  \"left\": {\n    \"num\": 1\n  },\n  \"right\": {\n    \"num\": 2\n  }\n}"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn end_of_input_ends_the_session() {
+        // No `sair` and no final newline: the session still ends.
+        let out = session("2 * 3", &mut ReplState::default());
+        assert!(out.ends_with("Resultado: 6\n\n"));
+
+        struct Failing;
+        impl io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk on fire"))
+            }
+        }
+        let result = run_interactive(
+            io::BufReader::new(Failing),
+            &mut Vec::new(),
+            &mut ReplState::default(),
+            &ReplConfig::default(),
+        );
+        assert_eq!(result.unwrap_err().to_string(), "disk on fire");
+    }
+
+    #[test]
+    fn quiet_mode_prints_no_banner_or_prompt() {
+        let run = |quiet| {
+            let config = ReplConfig {
+                prompt: "> ".to_string(),
+                quiet,
+                ..ReplConfig::default()
+            };
+            let mut out = Vec::new();
+            run_interactive(
+                "1 + 1\n".as_bytes(),
+                &mut out,
+                &mut ReplState::default(),
+                &config,
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let loud = run(false);
+        assert!(loud.starts_with("=== Calculadora de Expressões ===\n"));
+        assert!(loud.contains("\n> "));
+        let quiet = run(true);
+        assert!(quiet.starts_with("\nExpressão simplificada:\n"));
+        assert!(!quiet.contains("> "));
+    }
+
+    #[test]
+    fn debug_tree_for_ast_only() {
+        assert_eq!(
+            ast_only("1 + 2", &LexerConfig::default()).unwrap(),
+            "Addition(\n    Number(\n        1,\n    ),\n    Number(\n        2,\n    ),\n)"
+        );
+        assert_eq!(
+            ast_only("1 +", &LexerConfig::default()),
+            Err(ReplError::Parse(ParseError::ExpectedOperand {
+                operator: crate::parser::Token::Plus,
+                pos: 3,
+            }))
+        );
+    }
+
+    #[test]
+    fn lint_parens_reports_columns() {
+        let mut state = ReplState {
+            lint_parens: true,
+            ..ReplState::default()
+        };
+        let out = session("(2 + 3)\n(2 + 3) * 4\n", &mut state);
+        // Only the first line has parentheses to drop.
+        assert_eq!(out.matches("Parênteses redundantes").count(), 1);
+        assert!(out.contains("Parênteses redundantes na coluna 1\n"));
+    }
+
+    #[test]
+    fn shows_the_failing_subexpression() {
+        let out = session("1 + (10 / 0)\n", &mut ReplState::default());
+        assert!(out.contains(
+            "Erro ao avaliar: Division by zero in '/'\nSubexpressão que falhou: `10 / 0`\n"
+        ));
+    }
+
+    #[test]
+    fn failing_subexpression_follows_mode_and_width() {
+        let mut state = ReplState::default();
+        handle_command(":mode wrapping", &mut state);
+        let out = session("a = 9223372036854775807 + 1; 1 / 0\n", &mut state);
+        assert!(out.contains("Subexpressão que falhou: `1 / 0`\n"));
+
+        // Only 32-bit arithmetic overflows here.
+        let mut state = ReplState::default();
+        handle_command(":width 32", &mut state);
+        let out = session("1 + (2147483647 + 1)\n", &mut state);
+        assert!(out.contains(
+            "Erro ao avaliar: Overflow in '+'\nSubexpressão que falhou: `2147483647 + 1`\n"
+        ));
+    }
+
+    #[test]
+    fn parens_command() {
+        assert_eq!(
+            output(":parens 2 + 3 * 4"),
+            CommandResult::Output("(2 + (3 * 4))".to_string())
+        );
+    }
+
+    #[test]
+    fn json_responses() {
+        let mut state = ReplState::default();
+        assert_eq!(
+            json_response("1 + 2", &mut state),
+            r#"{"input":"1 + 2","result":3,"error":null,"tree":{"Addition":[{"Number":1},{"Number":2}]}}"#
+        );
+        assert_eq!(
+            json_response("1 / 0", &mut state),
+            r#"{"input":"1 / 0","result":null,"error":"Division by zero in '/'","tree":{"Division":[{"Number":1},{"Number":0}]}}"#
+        );
+        assert_eq!(
+            json_response("1 +", &mut state),
+            r#"{"input":"1 +","result":null,"error":"Expected an operand after '+' at position 3","tree":null}"#
+        );
+    }
+
+    #[test]
+    fn json_mode_answers_commands_in_json() {
+        let config = ReplConfig {
+            json: true,
+            ..ReplConfig::default()
+        };
+        let mut out = Vec::new();
+        let input = ":base 16\n:base 7\n:sexpr 1 + 2\n10\n:quit\n";
+        run_interactive(
+            input.as_bytes(),
+            &mut out,
+            &mut ReplState::default(),
+            &config,
+        )
+        .unwrap();
+        let lines: Vec<_> = std::str::from_utf8(&out).unwrap().lines().collect();
+        assert_eq!(
+            lines,
+            [
+                r#"{"input":":base 16","output":"Base de saída: 16","error":null}"#,
+                r#"{"input":":base 7","output":null,"error":"Bad arguments for ':base': expected one of 2, 8, 10 or 16"}"#,
+                r#"{"input":":sexpr 1 + 2","output":"(+ 1 2)","error":null}"#,
+                r#"{"input":"10","result":10,"error":null,"tree":{"Number":10}}"#,
+            ]
+        );
+    }
+}
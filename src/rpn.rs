@@ -0,0 +1,448 @@
+use std::fmt;
+
+use crate::eval::builtin_arity;
+use crate::expression::{BinOp, CompareOp, Expression};
+use crate::parser::{ParseError, Parser, Token, decimal_expression, lex_with_spans};
+
+/// One entry of a postfix (reverse Polish) stream: operands are pushed and
+/// each operator pops its operands and pushes the result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpnItem {
+    Operand(i64),
+    Variable(String),
+    Operator(OpKind),
+    /// Pops a value and binds the name to it until the matching `Unbind`,
+    /// so the items of a `let` value appear once however often it is used.
+    Bind(String),
+    /// Drops the innermost binding, leaving the body's value on the stack.
+    Unbind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpKind {
+    Binary(BinOp),
+    Compare(CompareOp),
+    Negate,
+    Factorial,
+    Call {
+        name: String,
+        arity: usize,
+    },
+    /// Pops the condition and both branches, in that order of pushing, and
+    /// keeps the branch the condition picks.
+    Select,
+}
+
+impl OpKind {
+    pub fn arity(&self) -> usize {
+        match self {
+            OpKind::Negate | OpKind::Factorial => 1,
+            OpKind::Binary(_) | OpKind::Compare(_) => 2,
+            OpKind::Call { arity, .. } => *arity,
+            OpKind::Select => 3,
+        }
+    }
+
+    // Each operator is applied by evaluating it over literal operands, so the
+    // stack machine shares every rule (overflow, division by zero, builtins)
+    // with `evaluate`.
+    fn apply(&self, operands: &[i64]) -> Option<i64> {
+        let mut literals = operands.iter().map(|&n| Expression::Number(n));
+        let mut next = || literals.next().expect("operand count checked");
+        let node = match self {
+            OpKind::Binary(op) => Expression::binary(*op, next(), next()),
+            OpKind::Compare(op) => Expression::compare(*op, next(), next()),
+            OpKind::Negate => Expression::neg(next()),
+            OpKind::Factorial => Expression::factorial(next()),
+            OpKind::Call { name, .. } => {
+                Expression::call(name, operands.iter().map(|&n| n.into()).collect())
+            }
+            OpKind::Select => Expression::conditional(next(), next(), next()),
+        };
+        node.evaluate().ok()
+    }
+}
+
+impl fmt::Display for RpnItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RpnItem::Operand(n) => write!(f, "{}", n),
+            RpnItem::Variable(name) => write!(f, "{}", name),
+            RpnItem::Operator(OpKind::Binary(op)) => write!(f, "{}", op.symbol()),
+            RpnItem::Operator(OpKind::Compare(op)) => write!(f, "{}", op.symbol()),
+            RpnItem::Operator(OpKind::Negate) => write!(f, "neg"),
+            RpnItem::Operator(OpKind::Factorial) => write!(f, "!"),
+            RpnItem::Operator(OpKind::Call { name, .. }) => write!(f, "{}", name),
+            RpnItem::Operator(OpKind::Select) => write!(f, "?"),
+            RpnItem::Bind(name) => write!(f, "let {}", name),
+            RpnItem::Unbind => write!(f, "in"),
+        }
+    }
+}
+
+impl Expression {
+    /// The postfix items separated by spaces, as in `2 3 4 * +`. Operators
+    /// are written as symbols, except `neg` for negation, `?` for a
+    /// conditional and the function's name for a call. A `let` becomes its
+    /// value, `let name`, its body and `in`, as in `3 let x x x * in`.
+    pub fn to_rpn(&self) -> String {
+        let items: Vec<String> = self.to_rpn_tokens().iter().map(|i| i.to_string()).collect();
+        items.join(" ")
+    }
+
+    /// Reads space-separated postfix notation, as written by `to_rpn`. An
+    /// identifier naming a builtin is a call taking the builtin's number of
+    /// arguments and `neg` is negation, so neither can be a variable here.
+    pub fn from_rpn(input: &str) -> Result<Expression, ParseError> {
+        // Each value on the stack keeps where its source text starts.
+        let mut stack: Vec<(Expression, usize)> = Vec::new();
+        // Each `let` waiting for its `in`, with its value and the stack height
+        // its body starts at. Operators in the body can't reach below it.
+        let mut pending: Vec<(String, (Expression, usize), usize)> = Vec::new();
+        let mut tokens = lex_with_spans(input)?.into_iter().peekable();
+        while let Some((token, span)) = tokens.next() {
+            let pos = span.start;
+            let floor = pending.last().map_or(0, |&(_, _, height)| height);
+            // Errors name the operator as written, `neg` rather than `-`.
+            let written = token.clone();
+            let (operator, arity) = match token {
+                Token::Let => {
+                    let Some((Token::Identifier(name), _)) = tokens.next() else {
+                        return Err(ParseError::InvalidAssignmentTarget { pos });
+                    };
+                    if stack.len() == floor {
+                        return Err(ParseError::WrongArity {
+                            operator: Token::Let,
+                            found: 0,
+                            pos,
+                        });
+                    }
+                    let value = stack.pop().expect("stack checked above");
+                    pending.push((name, value, stack.len()));
+                    continue;
+                }
+                Token::In => {
+                    let Some((name, (value, start), height)) = pending.pop() else {
+                        return Err(ParseError::UnexpectedToken {
+                            token: Token::In,
+                            pos,
+                        });
+                    };
+                    let body = match &stack[height..] {
+                        [(body, _)] => body.clone(),
+                        [] => {
+                            return Err(ParseError::WrongArity {
+                                operator: Token::In,
+                                found: 0,
+                                pos,
+                            });
+                        }
+                        [_, (_, pos), ..] => {
+                            return Err(ParseError::LeftoverOperands {
+                                count: stack.len() - height,
+                                pos: *pos,
+                            });
+                        }
+                    };
+                    stack.truncate(height);
+                    stack.push((Expression::let_in(&name, value, body), start));
+                    continue;
+                }
+                Token::Number(n) => {
+                    let value = i64::try_from(n).map_err(|_| ParseError::NumberTooLarge {
+                        literal: n.to_string(),
+                        pos,
+                    })?;
+                    stack.push((Expression::Number(value), pos));
+                    continue;
+                }
+                Token::Decimal { mantissa, scale } => {
+                    let expr = decimal_expression(mantissa, scale).ok_or_else(|| {
+                        ParseError::NumberTooLarge {
+                            literal: written.to_string(),
+                            pos,
+                        }
+                    })?;
+                    stack.push((expr, pos));
+                    continue;
+                }
+                // A `-` directly followed by digits is a negative literal.
+                Token::Minus
+                    if let Some(&(Token::Number(n), ref next)) = tokens.peek()
+                        && next.start == span.end =>
+                {
+                    tokens.next();
+                    let value =
+                        0i64.checked_sub_unsigned(n)
+                            .ok_or_else(|| ParseError::NumberTooLarge {
+                                literal: format!("-{}", n),
+                                pos,
+                            })?;
+                    stack.push((Expression::Number(value), pos));
+                    continue;
+                }
+                Token::Identifier(name) if name == "neg" => (Token::Minus, 1),
+                Token::Identifier(name) => match builtin_arity(&name) {
+                    Some(arity) => (Token::Identifier(name), arity),
+                    None => {
+                        stack.push((Expression::Variable(name), pos));
+                        continue;
+                    }
+                },
+                Token::Bang => (Token::Bang, 1),
+                Token::Question => (Token::Question, 3),
+                token @ (Token::Plus
+                | Token::Minus
+                | Token::Times
+                | Token::Divide
+                | Token::Modulo
+                | Token::Power
+                | Token::Comparison(_)) => (token, 2),
+                token => return Err(ParseError::UnexpectedToken { token, pos }),
+            };
+
+            let Some(first) = stack
+                .len()
+                .checked_sub(arity)
+                .filter(|&first| first >= floor)
+            else {
+                return Err(ParseError::WrongArity {
+                    operator: written,
+                    found: stack.len() - floor,
+                    pos,
+                });
+            };
+            let operands = stack.split_off(first);
+            let start = operands.first().map_or(pos, |&(_, start)| start);
+            let operands = operands.into_iter().map(|(expr, _)| expr).collect();
+            stack.push((Parser::build_sexpr_node(operator, operands, pos)?, start));
+        }
+
+        if !pending.is_empty() {
+            return Err(ParseError::Expected {
+                expected: Token::In,
+                found: None,
+                pos: input.len(),
+            });
+        }
+        match stack.as_slice() {
+            [] => Err(ParseError::InvalidExpression { pos: input.len() }),
+            [(expr, _)] => Ok(expr.clone()),
+            [_, (_, pos), ..] => Err(ParseError::LeftoverOperands {
+                count: stack.len(),
+                pos: *pos,
+            }),
+        }
+    }
+
+    /// The tree in postfix order, children before their operator. A `let`
+    /// is its value, a `Bind`, its body and an `Unbind`.
+    pub fn to_rpn_tokens(&self) -> Vec<RpnItem> {
+        let mut items = Vec::new();
+        self.write_rpn(&mut items);
+        items
+    }
+
+    fn write_rpn(&self, items: &mut Vec<RpnItem>) {
+        match self {
+            Expression::Variable(name) => return items.push(RpnItem::Variable(name.clone())),
+            Expression::Let { name, value, body } => {
+                value.write_rpn(items);
+                items.push(RpnItem::Bind(name.clone()));
+                body.write_rpn(items);
+                return items.push(RpnItem::Unbind);
+            }
+            _ => {}
+        }
+        for child in self.children() {
+            child.write_rpn(items);
+        }
+        let kind = match self {
+            Expression::Number(n) => return items.push(RpnItem::Operand(*n)),
+            Expression::Negation(_) => OpKind::Negate,
+            Expression::Factorial(_) => OpKind::Factorial,
+            Expression::Call { name, args } => OpKind::Call {
+                name: name.clone(),
+                arity: args.len(),
+            },
+            Expression::Comparison { op, .. } => OpKind::Compare(*op),
+            Expression::Conditional { .. } => OpKind::Select,
+            _ => {
+                let (op, _, _) = self.as_binary().expect("every other variant is binary");
+                OpKind::Binary(op)
+            }
+        };
+        items.push(RpnItem::Operator(kind));
+    }
+}
+
+/// Runs a postfix stream on a stack of values. Returns `None` for an unbound
+/// variable, a failing operator or a stream that does not leave exactly one
+/// value. Every operand has been computed by the time its operator runs, so
+/// unlike `evaluate` a failure in the branch not taken, or next to a zero
+/// factor, still fails.
+pub fn eval_rpn(items: &[RpnItem]) -> Option<i64> {
+    let mut stack = Vec::new();
+    let mut bindings: Vec<(&str, i64)> = Vec::new();
+    for item in items {
+        let value = match item {
+            RpnItem::Operand(n) => *n,
+            RpnItem::Variable(name) => {
+                let (_, value) = bindings.iter().rev().find(|(bound, _)| bound == name)?;
+                *value
+            }
+            RpnItem::Operator(kind) => {
+                let operands = stack.split_off(stack.len().checked_sub(kind.arity())?);
+                kind.apply(&operands)?
+            }
+            RpnItem::Bind(name) => {
+                bindings.push((name, stack.pop()?));
+                continue;
+            }
+            RpnItem::Unbind => {
+                bindings.pop()?;
+                continue;
+            }
+        };
+        stack.push(value);
+    }
+    match stack.as_slice() {
+        [value] => Some(*value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input)
+            .and_then(|mut parser| parser.parse())
+            .expect("test input parses")
+    }
+
+    #[test]
+    fn stack_machine_matches_evaluate() {
+        for input in [
+            "2 * 3 + 4",
+            "1 < 2 ? -5! : max(1, 2)",
+            "let x = 3 in x * x",
+            "let x = 1 in let y = x + 1 in x * y",
+            "let x = 2 in (let x = 5 in x) + x",
+            "1 / 0",
+        ] {
+            let expr = parse(input);
+            assert_eq!(
+                eval_rpn(&expr.to_rpn_tokens()),
+                expr.evaluate().ok(),
+                "input {:?}",
+                input
+            );
+        }
+        assert_eq!(
+            parse("let x = 3 in x * x").to_rpn_tokens(),
+            [
+                RpnItem::Operand(3),
+                RpnItem::Bind("x".to_string()),
+                RpnItem::Variable("x".to_string()),
+                RpnItem::Variable("x".to_string()),
+                RpnItem::Operator(OpKind::Binary(BinOp::Mul)),
+                RpnItem::Unbind,
+            ]
+        );
+        assert_eq!(eval_rpn(&parse("x + 1").to_rpn_tokens()), None);
+    }
+
+    #[test]
+    fn let_errors() {
+        let error = |input| Expression::from_rpn(input).unwrap_err();
+        assert_eq!(
+            error("3 let 4 x in"),
+            ParseError::InvalidAssignmentTarget { pos: 2 }
+        );
+        assert_eq!(
+            error("let x x in"),
+            ParseError::WrongArity {
+                operator: Token::Let,
+                found: 0,
+                pos: 0,
+            }
+        );
+        assert_eq!(
+            error("1 in"),
+            ParseError::UnexpectedToken {
+                token: Token::In,
+                pos: 2,
+            }
+        );
+        assert_eq!(
+            error("3 let x in"),
+            ParseError::WrongArity {
+                operator: Token::In,
+                found: 0,
+                pos: 8,
+            }
+        );
+        assert_eq!(
+            error("3 let x 1 2 in"),
+            ParseError::LeftoverOperands { count: 2, pos: 10 }
+        );
+        assert_eq!(
+            error("3 let x x"),
+            ParseError::Expected {
+                expected: Token::In,
+                found: None,
+                pos: 9,
+            }
+        );
+        // Operators in the body can't reach below where it starts.
+        assert_eq!(
+            error("3 let x + in"),
+            ParseError::WrongArity {
+                operator: Token::Plus,
+                found: 0,
+                pos: 8,
+            }
+        );
+    }
+
+    #[test]
+    fn from_rpn_round_trip() {
+        assert_eq!(Expression::from_rpn("2 3 4 * +"), Ok(parse("2 + 3 * 4")));
+        for input in [
+            "2 + 3 * 4",
+            "-(1 - 2) ** 3!",
+            "max(-5, 2) % 3",
+            "x < 1 ? y : 2",
+        ] {
+            let expr = parse(input);
+            assert_eq!(
+                Expression::from_rpn(&expr.to_rpn()),
+                Ok(expr),
+                "input {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn from_rpn_errors() {
+        assert_eq!(
+            Expression::from_rpn("2 +"),
+            Err(ParseError::WrongArity {
+                operator: Token::Plus,
+                found: 1,
+                pos: 2,
+            })
+        );
+        assert_eq!(
+            Expression::from_rpn("1 2 3 +"),
+            Err(ParseError::LeftoverOperands { count: 2, pos: 2 })
+        );
+        assert_eq!(
+            Expression::from_rpn(""),
+            Err(ParseError::InvalidExpression { pos: 0 })
+        );
+    }
+}
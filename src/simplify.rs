@@ -0,0 +1,311 @@
+use crate::eval::EvalError;
+use crate::expression::{BinOp, Expression};
+
+impl Expression {
+    /// Folds constant subtrees into literals and combines the constants of a
+    /// `+` or `*` chain, as in `1 + x + 2` → `x + 3`. A negated literal such
+    /// as `-5` becomes a single negative literal, and a `let` binding a
+    /// literal is replaced by its body with the value substituted. Subtrees
+    /// whose evaluation fails are kept so the simplified tree fails the same
+    /// way.
+    pub fn simplify(&self) -> Expression {
+        let expr = self.map_children(|child| child.simplify());
+        if expr.is_constant()
+            && let Ok(value) = expr.evaluate()
+        {
+            return Expression::Number(value);
+        }
+        match &expr {
+            Expression::Conditional { cond, then, els } => match **cond {
+                Expression::Number(0) => (**els).clone(),
+                Expression::Number(_) => (**then).clone(),
+                _ => expr,
+            },
+            Expression::Let { name, value, body } => match **value {
+                Expression::Number(n) => body.substitute(name, n).simplify(),
+                _ => expr,
+            },
+            Expression::Addition(..) => expr.reassociate(BinOp::Add).unwrap_or(expr),
+            Expression::Multiplication(..) => expr.reassociate(BinOp::Mul).unwrap_or(expr),
+            _ => expr,
+        }
+    }
+
+    /// True when both trees are the same after folding constant subtrees and
+    /// ordering the operands of `+` and `*`, so `2 + 3` matches `3 + 2` and
+    /// `x * (2 * 3)` matches `6 * x`.
+    pub fn is_equivalent(&self, other: &Expression) -> bool {
+        self.normalized() == other.normalized()
+    }
+
+    /// A deterministic normal form for `+` and `*`: every chain of one of
+    /// them is flattened and its operands sorted by `Ord`, which puts
+    /// literals first (ascending), then variables, then other subtrees. The
+    /// other operators keep their shape and only have their operands
+    /// canonicalized. Regrouping a chain can move where it overflows:
+    /// `(i64::MAX + 1) + -1` fails while its normal form `(-1 + 1) +
+    /// i64::MAX` does not.
+    pub fn canonicalize(&self) -> Expression {
+        let expr = self.map_children(|child| child.canonicalize());
+        match &expr {
+            Expression::Addition(..) => expr.sort_chain(BinOp::Add),
+            Expression::Multiplication(..) => expr.sort_chain(BinOp::Mul),
+            _ => expr,
+        }
+    }
+
+    fn sort_chain(&self, op: BinOp) -> Expression {
+        let mut operands = Vec::new();
+        self.collect_chain(op, &mut operands);
+        operands.sort();
+        let mut operands = operands.into_iter().cloned();
+        let first = operands.next().expect("a chain has at least two operands");
+        operands.fold(first, |acc, operand| Expression::binary(op, acc, operand))
+    }
+
+    /// Performs one evaluation step: the leftmost operator whose operands are
+    /// all literals is replaced by its value, a conditional with a literal
+    /// condition by the branch it picks and a `let` binding a literal by its
+    /// body with the value substituted. Returns `None` once no step is left
+    /// and the error when the step itself fails, as for `1 / 0`.
+    pub fn reduce_step(&self) -> Result<Option<Expression>, EvalError> {
+        if let Expression::Conditional { cond, then, els } = self {
+            return match **cond {
+                Expression::Number(0) => Ok(Some((**els).clone())),
+                Expression::Number(_) => Ok(Some((**then).clone())),
+                // The branches are left alone until the condition picks one.
+                _ => Ok(cond.reduce_step()?.map(|cond| Expression::Conditional {
+                    cond: Box::new(cond),
+                    then: then.clone(),
+                    els: els.clone(),
+                })),
+            };
+        }
+        if let Expression::Let { name, value, body } = self
+            && let Expression::Number(n) = **value
+        {
+            return Ok(Some(body.substitute(name, n)));
+        }
+        // A zero factor decides the product like in `evaluate`: one on the
+        // left before the right is reduced, one on the right once the left
+        // overflows.
+        if let Expression::Multiplication(left, _) = self
+            && let Expression::Number(0) = **left
+        {
+            return Ok(Some(Expression::Number(0)));
+        }
+
+        let children = self.children();
+        for (i, child) in children.iter().enumerate() {
+            let step = match (self, child.reduce_step()) {
+                (Expression::Multiplication(_, right), Err(EvalError::Overflow(_)))
+                    if i == 0 && right.evaluate() == Ok(0) =>
+                {
+                    return Ok(Some(Expression::Number(0)));
+                }
+                (_, step) => step?,
+            };
+            if let Some(reduced) = step {
+                let mut reduced = Some(reduced);
+                let mut index = 0;
+                return Ok(Some(self.map_children(|child| {
+                    let next = match reduced.take_if(|_| index == i) {
+                        Some(reduced) => reduced,
+                        None => child.clone(),
+                    };
+                    index += 1;
+                    next
+                })));
+            }
+        }
+        if children.is_empty() || !children.iter().all(|c| matches!(c, Expression::Number(_))) {
+            return Ok(None);
+        }
+        self.evaluate().map(|value| Some(Expression::Number(value)))
+    }
+
+    fn normalized(&self) -> Expression {
+        let expr = self.map_children(|child| child.normalized());
+        if expr.is_constant()
+            && let Ok(value) = expr.evaluate()
+        {
+            return Expression::Number(value);
+        }
+        match expr {
+            Expression::Addition(l, r) if r < l => Expression::Addition(r, l),
+            Expression::Multiplication(l, r) if r < l => Expression::Multiplication(r, l),
+            _ => expr,
+        }
+    }
+
+    // Reordering can move where a checked operation overflows, so a chain is
+    // only rewritten when that provably cannot matter: exactly one operand is
+    // not a literal, and the literals are all of one sign for `+` or all at
+    // least 1 for `*`. Every intermediate result then lies between that
+    // operand and the final value, so both trees overflow (or not) together.
+    fn reassociate(&self, op: BinOp) -> Option<Expression> {
+        let mut operands = Vec::new();
+        self.collect_chain(op, &mut operands);
+
+        let mut constants = Vec::new();
+        let mut others = Vec::new();
+        for operand in operands {
+            match operand {
+                Expression::Number(n) => constants.push(*n),
+                _ => others.push(operand),
+            }
+        }
+        let [other] = others.as_slice() else {
+            return None;
+        };
+        if constants.len() < 2 {
+            return None;
+        }
+
+        let combined = match op {
+            BinOp::Add => {
+                let same_sign =
+                    constants.iter().all(|&n| n >= 0) || constants.iter().all(|&n| n <= 0);
+                if !same_sign {
+                    return None;
+                }
+                constants
+                    .iter()
+                    .try_fold(0i64, |acc, &n| acc.checked_add(n))?
+            }
+            BinOp::Mul => {
+                if constants.iter().any(|&n| n < 1) {
+                    return None;
+                }
+                constants
+                    .iter()
+                    .try_fold(1i64, |acc, &n| acc.checked_mul(n))?
+            }
+            _ => return None,
+        };
+
+        Some(Expression::binary(
+            op,
+            (*other).clone(),
+            Expression::Number(combined),
+        ))
+    }
+
+    fn collect_chain<'a>(&'a self, op: BinOp, operands: &mut Vec<&'a Expression>) {
+        match self.as_binary() {
+            Some((node_op, left, right)) if node_op == op => {
+                left.collect_chain(op, operands);
+                right.collect_chain(op, operands);
+            }
+            _ => operands.push(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input)
+            .and_then(|mut parser| parser.parse())
+            .expect("test input parses")
+    }
+
+    #[test]
+    fn simplify_combines_chain_constants() {
+        let simplified = parse("1 + x + 2").simplify();
+        assert_eq!(simplified, parse("x + 3"));
+        assert!(simplified.literals().contains(&3));
+        assert_eq!(parse("2 * x * 3").simplify(), parse("x * 6"));
+        // Subtraction is not associative, so its chain is left alone.
+        assert_eq!(parse("1 - x - 2").simplify(), parse("1 - x - 2"));
+    }
+
+    #[test]
+    fn semantic_equivalence() {
+        assert!(parse("2 + 3").is_equivalent(&parse("3 + 2")));
+        assert!(!parse("2 - 3").is_equivalent(&parse("3 - 2")));
+        assert!(parse("2 * 3").is_equivalent(&parse("6")));
+        assert!(parse("x * (2 * 3)").is_equivalent(&parse("6 * x")));
+    }
+
+    #[test]
+    fn reduce_step_folds_innermost_first() {
+        let mut expr = parse("2 + 3 * 4");
+        let mut steps = vec![expr.to_string()];
+        while let Some(next) = expr.reduce_step().unwrap() {
+            steps.push(next.to_string());
+            expr = next;
+        }
+        assert_eq!(steps, ["2 + 3 * 4", "2 + 12", "14"]);
+        assert_eq!(
+            parse("(1 + 1) / (2 - 2)").reduce_step(),
+            Ok(Some(parse("2 / (2 - 2)")))
+        );
+        assert_eq!(
+            parse("2 / 0").reduce_step(),
+            Err(EvalError::DivisionByZero("/"))
+        );
+    }
+
+    #[test]
+    fn canonicalize_sorts_commutative_chains() {
+        assert_eq!(
+            parse("3 + 1 + 2").canonicalize(),
+            parse("1 + 2 + 3").canonicalize()
+        );
+        assert_eq!(
+            parse("x + 1 + y").canonicalize(),
+            parse("y + x + 1").canonicalize()
+        );
+        assert_eq!(
+            parse("2 * (3 + 1)").canonicalize(),
+            parse("(1 + 3) * 2").canonicalize()
+        );
+        // Subtraction keeps its operand order.
+        assert_ne!(parse("2 - 1").canonicalize(), parse("1 - 2").canonicalize());
+
+        let expr = parse("(4 - 2) * 5 + 3 * (7 - 1)");
+        assert_eq!(expr.canonicalize().evaluate(), expr.evaluate());
+    }
+
+    #[test]
+    fn negative_literals_fold() {
+        let simplified = parse("-5").simplify();
+        assert_eq!(simplified, Expression::num(-5));
+        assert_eq!(simplified.node_count(), 1);
+        assert_eq!(simplified.literals(), [-5]);
+        // `i64::MIN` has no positive counterpart to fold into.
+        let min = Expression::neg(Expression::num(i64::MIN));
+        assert_eq!(min.simplify(), min);
+    }
+
+    #[test]
+    fn reduce_step_short_circuits_zero_factors() {
+        let reduce = |input| {
+            let mut expr = parse(input);
+            while let Some(next) = expr.reduce_step()? {
+                expr = next;
+            }
+            Ok(expr)
+        };
+        assert_eq!(
+            reduce("0 * (9223372036854775807 * 2)"),
+            Ok(Expression::num(0))
+        );
+        assert_eq!(
+            reduce("(9223372036854775807 * 2) * 0"),
+            Ok(Expression::num(0))
+        );
+        assert_eq!(reduce("-(56 + 0 * (88! * 35))"), Ok(Expression::num(-56)));
+        assert_eq!(reduce("(1 / 0) * 0"), Err(EvalError::DivisionByZero("/")));
+        assert_eq!(
+            reduce("nosuchfn(1) * 0"),
+            Err(EvalError::UnknownFunction("nosuchfn".to_string()))
+        );
+        // A variable is not a constant, so there is nothing to fold.
+        assert_eq!(reduce("x * 0"), Ok(parse("x * 0")));
+    }
+}
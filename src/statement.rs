@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::eval::{Environment, EvalError, IntegerWidth, OverflowMode};
+use crate::expression::Expression;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    /// `value` is itself an assignment in a chain such as `a = b = 5`.
+    Assignment {
+        name: String,
+        value: Box<Statement>,
+    },
+    Expression(Expression),
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::Assignment { name, value } => write!(f, "{} = {}", name, value),
+            Statement::Expression(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+impl Statement {
+    pub fn expression(&self) -> &Expression {
+        match self {
+            Statement::Assignment { value, .. } => value.expression(),
+            Statement::Expression(expr) => expr,
+        }
+    }
+
+    pub fn execute(&self, env: &mut Environment, mode: OverflowMode) -> Result<i64, EvalError> {
+        let value = self.execute_width(env, mode, IntegerWidth::I64)?;
+        Ok(i64::try_from(value).expect("64-bit arithmetic yields an i64"))
+    }
+
+    pub fn execute_width(
+        &self,
+        env: &mut Environment,
+        mode: OverflowMode,
+        width: IntegerWidth,
+    ) -> Result<i128, EvalError> {
+        match self {
+            Statement::Assignment { name, value } => {
+                let value = value.execute_width(env, mode, width)?;
+                env.insert(name.clone(), value);
+                Ok(value)
+            }
+            Statement::Expression(expr) => expr.evaluate_width_with(env, mode, width),
+        }
+    }
+}
+
+// Runs the statements in order against a shared environment and returns the
+// value of the last one, or `None` for an empty program.
+pub fn run_program(
+    program: &[Statement],
+    env: &mut Environment,
+    mode: OverflowMode,
+) -> Result<Option<i64>, EvalError> {
+    let mut last = None;
+    for statement in program {
+        last = Some(statement.execute(env, mode)?);
+    }
+    Ok(last)
+}
+
+pub fn run_program_width(
+    program: &[Statement],
+    env: &mut Environment,
+    mode: OverflowMode,
+    width: IntegerWidth,
+) -> Result<Option<i128>, EvalError> {
+    let mut last = None;
+    for statement in program {
+        last = Some(statement.execute_width(env, mode, width)?);
+    }
+    Ok(last)
+}
+
+/// Rewrites `program` with the value of every variable whose assignment is
+/// constant so far substituted into the statements after it, each simplified,
+/// so `a = 2; b = a + 3; b * 2` becomes `a = 2; b = 5; 10`. A later
+/// non-constant assignment stops the propagation of that variable.
+pub fn propagate_constants(program: &[Statement]) -> Vec<Statement> {
+    let mut known = HashMap::new();
+    program
+        .iter()
+        .map(|statement| statement.propagate(&mut known))
+        .collect()
+}
+
+impl Statement {
+    fn propagate(&self, known: &mut HashMap<String, i64>) -> Statement {
+        match self {
+            Statement::Assignment { name, value } => {
+                let value = value.propagate(known);
+                match value.expression() {
+                    Expression::Number(n) => known.insert(name.clone(), *n),
+                    _ => known.remove(name),
+                };
+                Statement::Assignment {
+                    name: name.clone(),
+                    value: Box::new(value),
+                }
+            }
+            Statement::Expression(expr) => {
+                let expr = known.iter().fold(expr.clone(), |expr, (name, &value)| {
+                    expr.substitute(name, value)
+                });
+                Statement::Expression(expr.simplify())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParseError, Parser};
+
+    fn parse_program(input: &str) -> Vec<Statement> {
+        Parser::new(input)
+            .and_then(|mut parser| parser.parse_program())
+            .expect("test program parses")
+    }
+
+    fn run(input: &str) -> Result<Option<i64>, EvalError> {
+        run_program(
+            &parse_program(input),
+            &mut Environment::new(),
+            OverflowMode::Checked,
+        )
+    }
+
+    #[test]
+    fn programs() {
+        assert_eq!(run("2 * 3"), Ok(Some(6)));
+        assert_eq!(run("2 * 3;"), Ok(Some(6)));
+        assert_eq!(parse_program("2 * 3;").len(), 1);
+        assert_eq!(run("x = 4; y = x * 2; x + y"), Ok(Some(12)));
+        assert_eq!(run(""), Ok(None));
+    }
+
+    #[test]
+    fn assignment_chain() {
+        assert_eq!(
+            parse_program("a = b = 5"),
+            [Statement::Assignment {
+                name: "a".to_string(),
+                value: Box::new(Statement::Assignment {
+                    name: "b".to_string(),
+                    value: Box::new(Statement::Expression(Expression::num(5))),
+                }),
+            }]
+        );
+        let mut env = Environment::new();
+        let program = parse_program("a = b = 5");
+        assert_eq!(
+            run_program(&program, &mut env, OverflowMode::Checked),
+            Ok(Some(5))
+        );
+        assert_eq!((env["a"], env["b"]), (5, 5));
+
+        let parse = |input| Parser::new(input)?.parse_program();
+        assert_eq!(
+            parse("2 = 3"),
+            Err(ParseError::InvalidAssignmentTarget { pos: 0 })
+        );
+        assert_eq!(
+            parse("a = 2 = 3"),
+            Err(ParseError::InvalidAssignmentTarget { pos: 4 })
+        );
+    }
+
+    #[test]
+    fn constant_propagation() {
+        assert_eq!(
+            propagate_constants(&parse_program("a = 2; b = a + 3; b * 2")),
+            parse_program("a = 2; b = 5; 10")
+        );
+        // `b` keeps the value `a` had when it was assigned.
+        assert_eq!(
+            propagate_constants(&parse_program("a = 2; b = a; a = 5; a + b")),
+            parse_program("a = 2; b = 2; a = 5; 7")
+        );
+        // A value that isn't constant stops the propagation for its name.
+        assert_eq!(
+            propagate_constants(&parse_program("a = x; a + 1")),
+            parse_program("a = x; a + 1")
+        );
+    }
+}